@@ -0,0 +1,468 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{ Add, Sub, Mul, Neg, BitAnd, BitOr, BitXor };
+
+/// Each digit holds nine decimal digits, which keeps printing simple and
+/// lets the product of two digits fit in a `u64`.
+const BASE: u64 = 1_000_000_000;
+
+/// An integer of any size, for arithmetic that doesn't fit in 64 bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    /// Base 10^9 digits, least significant first, with no leading zeros.
+    /// Zero has no digits and is never negative.
+    digits: Vec<u32>
+}
+
+impl BigInt {
+    fn from_parts(negative: bool, mut digits: Vec<u32>) -> BigInt {
+        trim(&mut digits);
+        BigInt {
+            negative: negative && !digits.is_empty(),
+            digits
+        }
+    }
+
+    pub fn from_i64(n: i64) -> BigInt {
+        let mut magnitude = n.unsigned_abs();
+        let mut digits = Vec::new();
+        while magnitude > 0 {
+            digits.push((magnitude % BASE) as u32);
+            magnitude /= BASE;
+        }
+        BigInt::from_parts(n < 0, digits)
+    }
+
+    /// Parses unsigned digits in the given radix. Returns `None` if there are
+    /// no digits or any of them is invalid.
+    pub fn from_str_radix(digits: &str, radix: u32) -> Option<BigInt> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut magnitude = Vec::new();
+        for c in digits.chars() {
+            let digit = c.to_digit(radix)?;
+            magnitude = add_small(&multiply_small(&magnitude, radix), digit);
+        }
+        Some(BigInt::from_parts(false, magnitude))
+    }
+
+    /// The value as an `i64`, if it fits.
+    pub fn to_i64(&self) -> Option<i64> {
+        if self.digits.len() > 3 {
+            return None;
+        }
+        let magnitude = self.digits.iter().rev().fold(0i128, |acc, &digit| acc * BASE as i128 + digit as i128);
+        i64::try_from(if self.negative { -magnitude } else { magnitude }).ok()
+    }
+
+    /// The nearest float to the value.
+    pub fn to_f64(&self) -> f64 {
+        let magnitude = self.digits.iter().rev().fold(0.0, |acc, &digit| acc * BASE as f64 + digit as f64);
+        if self.negative { -magnitude } else { magnitude }
+    }
+
+    /// The number of bits in the magnitude, rounded up by a fraction of a
+    /// bit per digit. Zero has none.
+    pub fn approximate_bits(&self) -> u64 {
+        match self.digits.last() {
+            Some(&top) => (self.digits.len() as u64 - 1) * 30 + (32 - top.leading_zeros()) as u64,
+            None => 0
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.negative
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt::from_parts(false, self.digits.clone())
+    }
+
+    /// Division rounding the quotient down, so the remainder has the sign of
+    /// the divisor. Returns `None` when dividing by zero.
+    pub fn div_rem_floor(&self, divisor: &BigInt) -> Option<(BigInt, BigInt)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let (quotient, remainder) = divide_magnitudes(&self.digits, &divisor.digits);
+        if self.negative == divisor.negative || remainder.is_empty() {
+            Some((
+                BigInt::from_parts(self.negative != divisor.negative, quotient),
+                BigInt::from_parts(divisor.negative, remainder)
+            ))
+        }
+        else {
+            Some((
+                BigInt::from_parts(true, add_small(&quotient, 1)),
+                BigInt::from_parts(divisor.negative, subtract_magnitudes(&divisor.digits, &remainder))
+            ))
+        }
+    }
+
+    pub fn pow(&self, mut exponent: u32) -> BigInt {
+        let mut result = BigInt::from_i64(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = &result * &base;
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = &base * &base;
+            }
+        }
+        result
+    }
+
+    /// The greatest common divisor, which is never negative.
+    pub fn gcd(&self, other: &BigInt) -> BigInt {
+        let (mut a, mut b) = (self.abs(), other.abs());
+        while !b.is_zero() {
+            let remainder = divide_magnitudes(&a.digits, &b.digits).1;
+            a = b;
+            b = BigInt::from_parts(false, remainder);
+        }
+        a
+    }
+
+    /// Combines the two's complement bits of two integers with `op`, as if
+    /// both had infinitely many sign bits.
+    fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> BigInt {
+        let (a, b) = (to_words(&self.digits), to_words(&other.digits));
+        // one word more than either needs, so the top bit is always a sign bit
+        let width = a.len().max(b.len()) + 1;
+        let words: Vec<u32> = twos_complement(self.negative, &a, width).into_iter()
+            .zip(twos_complement(other.negative, &b, width))
+            .map(|(x, y)| op(x, y))
+            .collect();
+        let negative = words[width - 1] >> 31 == 1;
+        BigInt::from_parts(negative, from_words(&twos_complement(negative, &words, width)))
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::from_parts(self.negative, add_magnitudes(&self.digits, &other.digits));
+        }
+        match compare_magnitudes(&self.digits, &other.digits) {
+            Ordering::Less => BigInt::from_parts(other.negative, subtract_magnitudes(&other.digits, &self.digits)),
+            _ => BigInt::from_parts(self.negative, subtract_magnitudes(&self.digits, &other.digits))
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::from_parts(self.negative != other.negative, multiply_magnitudes(&self.digits, &other.digits))
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::from_parts(!self.negative, self.digits.clone())
+    }
+}
+
+impl BitAnd for &BigInt {
+    type Output = BigInt;
+
+    fn bitand(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |x, y| x & y)
+    }
+}
+
+impl BitOr for &BigInt {
+    type Output = BigInt;
+
+    fn bitor(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |x, y| x | y)
+    }
+}
+
+impl BitXor for &BigInt {
+    type Output = BigInt;
+
+    fn bitxor(self, other: &BigInt) -> BigInt {
+        self.bitwise(other, |x, y| x ^ y)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &BigInt) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => compare_magnitudes(&self.digits, &other.digits),
+            (true, true) => compare_magnitudes(&other.digits, &self.digits)
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &BigInt) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut digits = self.digits.iter().rev();
+        match digits.next() {
+            Some(first) => write!(f, "{}{}", if self.negative { "-" } else { "" }, first)?,
+            None => return write!(f, "0")
+        }
+        for digit in digits {
+            write!(f, "{:09}", digit)?;
+        }
+        Ok(())
+    }
+}
+
+fn trim(digits: &mut Vec<u32>) {
+    while digits.last() == Some(&0) {
+        digits.pop();
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+    for i in 0..a.len().max(b.len()) {
+        let sum = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        result.push((sum % BASE) as u32);
+        carry = sum / BASE;
+    }
+    result.push(carry as u32);
+    trim(&mut result);
+    result
+}
+
+/// `a - b`, where `a` must be at least as large as `b`.
+fn subtract_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+    for (i, &digit) in a.iter().enumerate() {
+        let subtrahend = *b.get(i).unwrap_or(&0) as i64 + borrow;
+        let mut difference = digit as i64 - subtrahend;
+        borrow = 0;
+        if difference < 0 {
+            difference += BASE as i64;
+            borrow = 1;
+        }
+        result.push(difference as u32);
+    }
+    trim(&mut result);
+    result
+}
+
+fn multiply_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut result = vec![0u64; a.len() + b.len()];
+    for (i, &x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, &y) in b.iter().enumerate() {
+            let product = result[i + j] + x as u64 * y as u64 + carry;
+            result[i + j] = product % BASE;
+            carry = product / BASE;
+        }
+        result[i + b.len()] = carry;
+    }
+    let mut result: Vec<u32> = result.into_iter().map(|digit| digit as u32).collect();
+    trim(&mut result);
+    result
+}
+
+fn multiply_small(a: &[u32], factor: u32) -> Vec<u32> {
+    multiply_magnitudes(a, &[factor])
+}
+
+fn add_small(a: &[u32], term: u32) -> Vec<u32> {
+    add_magnitudes(a, &[term])
+}
+
+/// Long division, one base 10^9 digit of the quotient at a time. Each digit
+/// is found by binary search, which is slow but simple.
+fn divide_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quotient = vec![0; a.len()];
+    let mut remainder: Vec<u32> = Vec::new();
+    for i in (0..a.len()).rev() {
+        remainder.insert(0, a[i]);
+        trim(&mut remainder);
+        let (mut low, mut high) = (0, (BASE - 1) as u32);
+        while low < high {
+            let middle = low + (high - low).div_ceil(2);
+            if compare_magnitudes(&multiply_small(b, middle), &remainder) == Ordering::Greater {
+                high = middle - 1;
+            }
+            else {
+                low = middle;
+            }
+        }
+        remainder = subtract_magnitudes(&remainder, &multiply_small(b, low));
+        quotient[i] = low;
+    }
+    trim(&mut quotient);
+    (quotient, remainder)
+}
+
+/// Converts base 10^9 digits to base 2^32 words, least significant first.
+fn to_words(digits: &[u32]) -> Vec<u32> {
+    let mut words = Vec::new();
+    let mut rest = digits.to_vec();
+    while !rest.is_empty() {
+        let mut remainder = 0;
+        for digit in rest.iter_mut().rev() {
+            let value = remainder * BASE + *digit as u64;
+            *digit = (value >> 32) as u32;
+            remainder = value & 0xFFFF_FFFF;
+        }
+        words.push(remainder as u32);
+        trim(&mut rest);
+    }
+    words
+}
+
+/// Converts base 2^32 words back to base 10^9 digits.
+fn from_words(words: &[u32]) -> Vec<u32> {
+    let mut digits = Vec::new();
+    for &word in words.iter().rev() {
+        let mut carry = word as u64;
+        for digit in digits.iter_mut() {
+            let value = ((*digit as u64) << 32) + carry;
+            *digit = (value % BASE) as u32;
+            carry = value / BASE;
+        }
+        while carry > 0 {
+            digits.push((carry % BASE) as u32);
+            carry /= BASE;
+        }
+    }
+    digits
+}
+
+/// `words` padded with zeros to `width`, then negated if `negative`.
+fn twos_complement(negative: bool, words: &[u32], width: usize) -> Vec<u32> {
+    let mut result = words.to_vec();
+    result.resize(width, 0);
+    if negative {
+        let mut carry = true;
+        for word in result.iter_mut() {
+            let (sum, overflowed) = (!*word).overflowing_add(carry as u32);
+            *word = sum;
+            carry = overflowed;
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(n: i64) -> BigInt {
+        BigInt::from_i64(n)
+    }
+
+    fn parse(digits: &str) -> BigInt {
+        match digits.strip_prefix('-') {
+            Some(digits) => -&BigInt::from_str_radix(digits, 10).unwrap(),
+            None => BigInt::from_str_radix(digits, 10).unwrap()
+        }
+    }
+
+    #[test]
+    fn div_rem_floor_rounds_down_for_every_sign() {
+        assert_eq!(big(7).div_rem_floor(&big(2)), Some((big(3), big(1))));
+        assert_eq!(big(-7).div_rem_floor(&big(2)), Some((big(-4), big(1))));
+        assert_eq!(big(7).div_rem_floor(&big(-2)), Some((big(-4), big(-1))));
+        assert_eq!(big(-7).div_rem_floor(&big(-2)), Some((big(3), big(-1))));
+        assert_eq!(big(-6).div_rem_floor(&big(2)), Some((big(-3), big(0))));
+        assert_eq!(big(0).div_rem_floor(&big(-5)), Some((big(0), big(0))));
+        assert_eq!(big(1).div_rem_floor(&big(0)), None);
+    }
+
+    #[test]
+    fn long_division_matches_multiplication() {
+        let dividend = parse("-123456789012345678901234567890123456789");
+        let divisor = parse("9876543210987654321");
+        let (quotient, remainder) = dividend.div_rem_floor(&divisor).unwrap();
+        assert_eq!(&(&quotient * &divisor) + &remainder, dividend);
+        assert!(!remainder.is_negative() && remainder < divisor);
+        assert_eq!(quotient, parse("-12499999886093750002"));
+    }
+
+    #[test]
+    fn i64_limits_round_trip() {
+        for &n in &[i64::MIN, i64::MIN + 1, -1, 0, 1, i64::MAX] {
+            assert_eq!(big(n).to_i64(), Some(n));
+            assert_eq!(big(n).to_string(), n.to_string());
+        }
+        assert_eq!((&big(i64::MIN) - &big(1)).to_i64(), None);
+        assert_eq!((&big(i64::MAX) + &big(1)).to_i64(), None);
+        assert_eq!(-&big(i64::MIN), parse("9223372036854775808"));
+    }
+
+    #[test]
+    fn from_str_radix_reads_each_radix() {
+        assert_eq!(BigInt::from_str_radix("1010", 2), Some(big(10)));
+        assert_eq!(BigInt::from_str_radix("777", 8), Some(big(511)));
+        assert_eq!(BigInt::from_str_radix("ff", 16), Some(big(255)));
+        assert_eq!(BigInt::from_str_radix("FF", 16), Some(big(255)));
+        assert_eq!(BigInt::from_str_radix("ffffffffffffffffffff", 16), Some(parse("1208925819614629174706175")));
+        assert_eq!(BigInt::from_str_radix("102", 2), None);
+        assert_eq!(BigInt::from_str_radix("", 10), None);
+    }
+
+    #[test]
+    fn gcd_is_never_negative() {
+        assert_eq!(big(-12).gcd(&big(18)), big(6));
+        assert_eq!(big(12).gcd(&big(-18)), big(6));
+        assert_eq!(big(0).gcd(&big(-5)), big(5));
+    }
+
+    #[test]
+    fn bitwise_operators_match_i64() {
+        let values = [0, 1, -1, 7, -8, 1_000_000_007, -1_000_000_007, i64::MAX, i64::MIN];
+        for &a in values.iter() {
+            for &b in values.iter() {
+                assert_eq!(&big(a) & &big(b), big(a & b));
+                assert_eq!(&big(a) | &big(b), big(a | b));
+                assert_eq!(&big(a) ^ &big(b), big(a ^ b));
+            }
+        }
+    }
+
+    #[test]
+    fn bitwise_operators_sign_extend() {
+        let two_to_the_100 = parse("1267650600228229401496703205376");
+        assert_eq!(&two_to_the_100 & &big(-1), two_to_the_100);
+        assert_eq!(&-&two_to_the_100 & &big(255), big(0));
+        assert_eq!(&-&two_to_the_100 | &big(1), &big(1) - &two_to_the_100);
+        assert_eq!(&two_to_the_100 ^ &-&two_to_the_100, -&(&two_to_the_100 + &two_to_the_100));
+    }
+}
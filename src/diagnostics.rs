@@ -0,0 +1,284 @@
+use std::fmt::Write;
+use std::io::{ self, IsTerminal };
+
+use parser::{ Position, Span, ParseError, ParseErrorKind, Keyword, OPERATORS };
+use engine::{ RuntimeError, RuntimeErrorKind };
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Error
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool
+}
+
+/// An error along with everything needed to explain it: labelled
+/// spans of source, and any notes or help to print underneath.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub level: Level,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+    /// Calls leading up to the error, innermost first.
+    pub trace: Vec<(String, Span)>
+}
+
+impl Diagnostic {
+    pub fn new(level: Level, message: &str) -> Diagnostic {
+        Diagnostic {
+            level,
+            message: message.to_string(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+            help: Vec::new(),
+            trace: Vec::new()
+        }
+    }
+
+    pub fn error(message: &str) -> Diagnostic {
+        Diagnostic::new(Level::Error, message)
+    }
+
+    /// Underlines `span` with carets, this is where the problem is.
+    pub fn with_label(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label { span, message: message.to_string(), primary: true });
+        self
+    }
+
+    /// Underlines `span` with dashes, for related code like "function
+    /// defined here".
+    pub fn with_secondary_label(mut self, span: Span, message: &str) -> Diagnostic {
+        self.labels.push(Label { span, message: message.to_string(), primary: false });
+        self
+    }
+
+    pub fn with_note(mut self, note: &str) -> Diagnostic {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: &str) -> Diagnostic {
+        self.help.push(help.to_string());
+        self
+    }
+
+    /// Adds a call to the stack trace. Calls should be added starting from
+    /// the innermost one.
+    pub fn with_frame(mut self, call: &str, call_site: Span) -> Diagnostic {
+        self.trace.push((call.to_string(), call_site));
+        self
+    }
+
+    /// Prints the diagnostic to stderr, in color if stderr is a terminal.
+    pub fn emit(&self, source: &str) {
+        eprint!("{}", self.render(source, io::stderr().is_terminal()));
+    }
+
+    /// Renders the diagnostic in the style of rustc, quoting the lines of
+    /// `source` that the labels point at.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| if color {
+            format!("{}{}{}", style, text, RESET)
+        }
+        else {
+            text.to_string()
+        };
+        let (level, level_style) = match self.level {
+            Level::Error => ("error", RED)
+        };
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        labels.sort_by_key(|label| (label.span.start.line, !label.primary));
+        let width = labels.iter()
+            .map(|label| label.span.start.line.to_string().len())
+            .max()
+            .unwrap_or(0);
+        let gutter = " ".repeat(width);
+        let bar = paint(BLUE, "|");
+
+        let mut output = String::new();
+        let _ = writeln!(output, "{}{}", paint(level_style, level), paint(BOLD, &format!(": {}", self.message)));
+
+        if let Some(primary) = labels.iter().find(|label| label.primary).or_else(|| labels.first()) {
+            let _ = writeln!(output, "{}{} {}", gutter, paint(BLUE, "-->"), primary.span.start);
+            let _ = writeln!(output, "{} {}", gutter, bar);
+        }
+
+        let mut previous_line = None;
+        // lines and columns count from 1, so a span on line 0 doesn't point
+        // anywhere in the source and gets no snippet
+        for label in labels.iter().filter(|label| label.span.start.line > 0) {
+            let line_number = label.span.start.line;
+            let line = source.lines().nth(line_number as usize - 1).unwrap_or("");
+            if previous_line != Some(line_number) {
+                if previous_line.is_some_and(|previous| previous + 1 < line_number) {
+                    let _ = writeln!(output, "{}", paint(BLUE, "..."));
+                }
+                let _ = writeln!(output, "{} {} {}", paint(BLUE, &format!("{:>width$}", line_number, width = width)), bar, line);
+                previous_line = Some(line_number);
+            }
+
+            let start = (label.span.start.column as usize).saturating_sub(1);
+            let length = if label.span.end.line == line_number {
+                (label.span.end.column as usize).saturating_sub(label.span.start.column as usize)
+            }
+            else {
+                line.chars().count().saturating_sub(start)
+            };
+            let (marker, style) = if label.primary { ("^", level_style) } else { ("-", BLUE) };
+            let underline = format!("{} {}", marker.repeat(length.max(1)), label.message);
+            let _ = writeln!(output, "{} {} {}{}", gutter, bar, " ".repeat(start), paint(style, underline.trim_end()));
+        }
+
+        if !labels.is_empty() && (!self.notes.is_empty() || !self.help.is_empty()) {
+            let _ = writeln!(output, "{} {}", gutter, bar);
+        }
+        for note in self.notes.iter() {
+            let _ = writeln!(output, "{} {} {}", gutter, paint(BLUE, "="), paint(BOLD, &format!("note: {}", note)));
+        }
+        for help in self.help.iter() {
+            let _ = writeln!(output, "{} {} {}", gutter, paint(BLUE, "="), paint(BOLD, &format!("help: {}", help)));
+        }
+        if !self.trace.is_empty() {
+            let _ = writeln!(output, "{}", paint(BOLD, "stack trace (innermost call first):"));
+        }
+        for (i, &(ref call, call_site)) in self.trace.iter().enumerate() {
+            let line = (call_site.start.line as usize).checked_sub(1).and_then(|i| source.lines().nth(i)).unwrap_or("");
+            let _ = writeln!(output, "{:>4}: {}", i, call);
+            let _ = writeln!(output, "      {} {}: {}", paint(BLUE, "at"), call_site.start, line.trim());
+        }
+        output.push('\n');
+        output
+    }
+}
+
+impl From<&ParseError> for Diagnostic {
+    fn from(error: &ParseError) -> Diagnostic {
+        let diagnostic = Diagnostic::error(&error.to_string());
+        let expected = match error.expected {
+            Some(ref expected) => format!("expected {}", expected),
+            None => "unexpected token".to_string()
+        };
+        match error.kind {
+            ParseErrorKind::UnexpectedToken =>
+                diagnostic.with_label(error.span, &expected),
+            ParseErrorKind::Unclosed(opening) =>
+                diagnostic
+                    .with_label(error.span, &expected)
+                    .with_secondary_label(opening, "unclosed bracket"),
+            ParseErrorKind::UnterminatedString(ref closing) => {
+                let start = error.span.start;
+                let quote = Position { column: start.column + 1, offset: start.offset + 1, ..start };
+                diagnostic
+                    .with_label(Span::new(start, quote), "string starts here")
+                    .with_help(&format!("add a closing `{}`", closing))
+            },
+            ParseErrorKind::UnterminatedComment => {
+                let start = error.span.start;
+                let opening = Position { column: start.column + 2, offset: start.offset + 2, ..start };
+                diagnostic
+                    .with_label(Span::new(start, opening), "comment starts here")
+                    .with_help("add a closing `]#`, block comments can be nested so each `#[` needs one")
+            },
+            ParseErrorKind::InvalidEscape(_) =>
+                diagnostic
+                    .with_label(error.span, "not a valid escape")
+                    .with_help("the escapes are `\\n` `\\t` `\\r` `\\0` `\\\\` `\\\"` `\\$` `\\xNN` (up to `\\x7F`) and `\\u{NNNN}`"),
+            ParseErrorKind::InvalidNumber(_) =>
+                diagnostic
+                    .with_label(error.span, "not a valid number")
+                    .with_help("numbers are written like `42`, `3.14`, `1_000_000`, `6.02e23`, `0xFF`, `0b1010` or `0o17`"),
+            ParseErrorKind::UnknownChar(_) =>
+                diagnostic.with_label(error.span, "not valid here"),
+            ParseErrorKind::UnknownOperator(_) => {
+                let operators: Vec<String> = OPERATORS.iter().map(|op| format!("`{}`", op)).collect();
+                diagnostic
+                    .with_label(error.span, "not a known operator")
+                    .with_note(&format!("the operators are {}", operators.join(" ")))
+            },
+            ParseErrorKind::Misplaced(Keyword::Return) =>
+                diagnostic.with_label(error.span, "not inside a function"),
+            ParseErrorKind::Misplaced(_) =>
+                diagnostic.with_label(error.span, "not inside a loop")
+        }
+    }
+}
+
+impl From<&RuntimeError> for Diagnostic {
+    fn from(error: &RuntimeError) -> Diagnostic {
+        let mut diagnostic = Diagnostic::error(&error.to_string());
+        for frame in error.trace.iter() {
+            diagnostic = diagnostic.with_frame(&frame.to_string(), frame.call_site);
+        }
+        match error.kind {
+            RuntimeErrorKind::TypeMismatch { ref expected, ref found, .. } =>
+                diagnostic.with_label(error.span, &format!("expected {}, found {}", expected, found)),
+            RuntimeErrorKind::UndefinedVariable(_) =>
+                diagnostic.with_label(error.span, "not found in this scope"),
+            RuntimeErrorKind::UndeclaredVariable(ref name) =>
+                diagnostic
+                    .with_label(error.span, "not declared in any enclosing scope")
+                    .with_help(&format!("declare it with `{} := ...`", name)),
+            RuntimeErrorKind::ArityMismatch { expected, definition, .. } =>
+                diagnostic
+                    .with_label(error.span, &format!("expected {} argument{}", expected, if expected == 1 { "" } else { "s" }))
+                    .with_secondary_label(definition, "function defined here"),
+            RuntimeErrorKind::NotCallable(_) =>
+                diagnostic.with_label(error.span, "not a function"),
+            RuntimeErrorKind::IndexOutOfRange { length: 0, .. } =>
+                diagnostic
+                    .with_label(error.span, "out of range")
+                    .with_note("the list is empty"),
+            RuntimeErrorKind::IndexOutOfRange { length, .. } =>
+                diagnostic
+                    .with_label(error.span, "out of range")
+                    .with_note(&format!("valid indices are 0 to {}", length - 1)),
+            RuntimeErrorKind::Overflow(_) =>
+                diagnostic.with_label(error.span, "the result would be too large to compute"),
+            RuntimeErrorKind::MissingKey(_) =>
+                diagnostic
+                    .with_label(error.span, "not found in this map")
+                    .with_help("check for the key first with `has(map, key)`"),
+            RuntimeErrorKind::DivisionByZero =>
+                diagnostic.with_label(error.span, "the divisor is zero"),
+            RuntimeErrorKind::UnsupportedOperator(_) =>
+                diagnostic.with_label(error.span, "cannot be evaluated yet"),
+            RuntimeErrorKind::Misplaced(Keyword::Return) =>
+                diagnostic.with_label(error.span, "not inside a function"),
+            RuntimeErrorKind::Misplaced(_) =>
+                diagnostic.with_label(error.span, "not inside a loop"),
+            RuntimeErrorKind::InvalidAssignment =>
+                diagnostic
+                    .with_label(error.span, "cannot assign to this")
+                    .with_help("only variables, list elements and map entries can be assigned to, and only variables can be declared with `:=`"),
+            RuntimeErrorKind::InvalidSyntax =>
+                diagnostic.with_label(error.span, "this could not be parsed")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_outside_the_source_are_rendered_without_a_snippet() {
+        let rendered = Diagnostic::error("oops")
+            .with_label(Span::default(), "here")
+            .with_frame("f()", Span::default())
+            .render("x := 1", false);
+        assert!(rendered.starts_with("error: oops\n"));
+        assert!(!rendered.contains("x := 1"));
+    }
+}
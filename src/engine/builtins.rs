@@ -0,0 +1,70 @@
+use super::{ Span, Environment, Value, NativeFunction, Rational, RuntimeError, RuntimeErrorKind, BigInt };
+
+/// Defines the functions every program starts out with.
+pub fn define_builtins(env: &Environment) {
+    env.def("print", Value::Native(NativeFunction::new("print", |args, _| {
+        for arg in args.iter() {
+            print!("{}", arg);
+        }
+        Ok(Value::Nil)
+    })));
+
+    env.def("println", Value::Native(NativeFunction::new("println", |args, _| {
+        for arg in args.iter() {
+            print!("{}", arg);
+        }
+        println!();
+        Ok(Value::Nil)
+    })));
+
+    // `rational(1, 3)` is the exact fraction 1/3, arithmetic on it stays
+    // exact until it is mixed with a float
+    env.def("rational", Value::Native(NativeFunction::new("rational", |args, span| {
+        let integer = |value: &Value| match *value {
+            Value::Integer(n) => Some(BigInt::from_i64(n)),
+            Value::BigInt(ref n) => Some(n.clone()),
+            _ => None
+        };
+        match (args.first().and_then(integer), args.get(1).and_then(integer)) {
+            (Some(numerator), Some(denominator)) if args.len() == 2 => match Rational::new(numerator, denominator) {
+                Some(r) => Ok(Value::from_rational(r)),
+                None => Err(RuntimeError::new(RuntimeErrorKind::DivisionByZero, span))
+            },
+            _ => Err(mismatch("`rational`", "two integers", &args, span))
+        }
+    })));
+
+    env.def("len", Value::Native(NativeFunction::new("len", |args, span| {
+        match args.as_slice() {
+            [Value::List(list)] => Ok(Value::Integer(list.borrow().len() as i64)),
+            [Value::Map(map)] => Ok(Value::Integer(map.borrow().len() as i64)),
+            [Value::Range(start, end)] => Ok(Value::Integer(end.saturating_sub(*start).max(0))),
+            [Value::String_(s)] => Ok(Value::Integer(s.chars().count() as i64)),
+            _ => Err(mismatch("`len`", "a list, map, string or range", &args, span))
+        }
+    })));
+
+    // `push(xs, x)` adds `x` to the end of `xs`, changing the list in place
+    env.def("push", Value::Native(NativeFunction::new("push", |args, span| {
+        match args.as_slice() {
+            [Value::List(list), value] => {
+                list.borrow_mut().push(value.clone());
+                Ok(Value::Nil)
+            },
+            _ => Err(mismatch("`push`", "a list and a value", &args, span))
+        }
+    })));
+
+    env.def("has", Value::Native(NativeFunction::new("has", |args, span| {
+        match args.as_slice() {
+            [Value::Map(map), Value::String_(key)] => Ok(Value::Boolean(map.borrow().contains_key(key))),
+            _ => Err(mismatch("`has`", "a map and a string", &args, span))
+        }
+    })));
+}
+
+/// The error for a builtin given the wrong arguments, listing their types.
+fn mismatch(function: &str, expected: &str, args: &[Value], span: Span) -> RuntimeError {
+    let found: Vec<&str> = args.iter().map(Value::type_name).collect();
+    RuntimeError::type_mismatch(function, expected, &format!("({})", found.join(", ")), span)
+}
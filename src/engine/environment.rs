@@ -1,57 +1,87 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
 
-use super::AST;
+use super::Value;
 
-pub struct Environment<'a> {
-    bindings: HashMap<String, AST>,
-    parent_environment: Option<&'a Environment<'a>>
+struct Scope {
+    bindings: RefCell<HashMap<String, Value>>,
+    parent_environment: Option<Environment>
 }
 
-impl<'a> Environment<'a> {
-    pub fn new(parent_environment: Option<&'a Environment>) -> Environment<'a> {
-        Environment {
-            bindings: HashMap::new(),
+/// A handle to a scope and, through it, the scopes enclosing it. Cloning the
+/// handle shares the scope rather than copying it, so closures, the top level
+/// of a program and anything else holding on to an environment all see each
+/// other's definitions.
+#[derive(Clone)]
+pub struct Environment(Rc<Scope>);
+
+impl Environment {
+    pub fn new(parent_environment: Option<Environment>) -> Environment {
+        Environment(Rc::new(Scope {
+            bindings: RefCell::new(HashMap::new()),
             parent_environment
-        }
+        }))
     }
 
-    pub fn dump(&self) {
-        println!("{:#?}", self.bindings);
-        if let Some(parent) = self.parent_environment {
-            parent.dump();
-        }
+    /// A new scope nested inside this one.
+    pub fn extend(&self) -> Environment {
+        Environment::new(Some(self.clone()))
     }
 
-    fn _has(&self, binding: &String) -> bool {
-        self.bindings.contains_key(binding)
+    fn has(&self, binding: &str) -> bool {
+        self.0.bindings.borrow().contains_key(binding)
     }
 
-    pub fn get(&self, binding: &String) -> AST {
-        if let Some(ast) = self.bindings.get(binding) {
-            ast.clone()
+    pub fn get(&self, binding: &str) -> Option<Value> {
+        if let Some(value) = self.0.bindings.borrow().get(binding) {
+            Some(value.clone())
         }
-        else if let Some(ref env) = self.parent_environment {
+        else if let Some(ref env) = self.0.parent_environment {
             env.get(binding)
         }
         else {
-            self.dump();
-            panic!(format!("Undefined variable '{}'", binding));
+            None
         }
     }
 
-    /*pub fn set(&mut self, name: &String, value: AST) {
+    /// Updates the nearest binding of `name`. Returns false if there is no
+    /// such binding.
+    pub fn set(&self, name: &str, value: Value) -> bool {
         if self.has(name) {
-            self.bindings.insert(name.clone(), value);
+            self.0.bindings.borrow_mut().insert(name.to_string(), value);
+            true
         }
-        else if let Some(ref mut env) = self.parent_environment {
-            env.set(name, value);
+        else if let Some(ref env) = self.0.parent_environment {
+            env.set(name, value)
         }
         else {
-            panic!(format!("Undeclared variable '{}'", name));
+            false
         }
-    }*/
+    }
 
-    pub fn def(&mut self, name: &String, value: AST) {
-        self.bindings.insert(name.clone(), value);
+    /// Creates a new binding in this scope, shadowing any outer ones.
+    pub fn def(&self, name: &str, value: Value) {
+        self.0.bindings.borrow_mut().insert(name.to_string(), value);
+    }
+}
+
+/// Only lists the names bound in each scope, since values can refer back to
+/// the environment.
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<String> = self.0.bindings.borrow().keys().cloned().collect();
+        names.sort();
+        let mut list = f.debug_list();
+        list.entry(&names);
+        let mut parent = self.0.parent_environment.clone();
+        while let Some(env) = parent {
+            let mut names: Vec<String> = env.0.bindings.borrow().keys().cloned().collect();
+            names.sort();
+            list.entry(&names);
+            parent = env.0.parent_environment.clone();
+        }
+        list.finish()
     }
 }
@@ -0,0 +1,128 @@
+use std::fmt;
+
+use super::{ Span, Keyword };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    /// `operation` was given a value of the wrong type.
+    TypeMismatch {
+        operation: String,
+        expected: String,
+        found: String
+    },
+    UndefinedVariable(String),
+    /// Assignment to a variable that was never declared with `:=`.
+    UndeclaredVariable(String),
+    /// A function was called with the wrong number of arguments, `definition`
+    /// is the span of the function being called.
+    ArityMismatch {
+        expected: usize,
+        found: usize,
+        definition: Span
+    },
+    NotCallable(String),
+    /// `index` is the index as it would be printed.
+    IndexOutOfRange {
+        index: String,
+        length: usize
+    },
+    /// An operation would need an absurd amount of memory, like
+    /// `2 ** 10_000_000_000` or `"ab" * 10_000_000_000` would.
+    Overflow(String),
+    /// Reading a key that isn't in a map.
+    MissingKey(String),
+    DivisionByZero,
+    UnsupportedOperator(String),
+    /// `break`, `continue` or `return` outside of a loop or function, in a
+    /// tree the parser reported errors for.
+    Misplaced(Keyword),
+    InvalidAssignment,
+    InvalidSyntax
+}
+
+/// A function call that was in progress when an error happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub call_site: Span,
+    /// The arguments the function was called with, formatted for display.
+    pub arguments: String
+}
+
+impl fmt::Display for Frame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}({})", self.function, self.arguments)
+    }
+}
+
+/// An error that stopped evaluation, along with the span of the expression
+/// that caused it. `trace` holds the calls the error escaped from, innermost
+/// first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub kind: RuntimeErrorKind,
+    pub span: Span,
+    pub trace: Vec<Frame>
+}
+
+impl RuntimeError {
+    pub fn new(kind: RuntimeErrorKind, span: Span) -> RuntimeError {
+        RuntimeError { kind, span, trace: Vec::new() }
+    }
+
+    /// Records that the error escaped from the call described by `frame`.
+    pub fn unwind(mut self, frame: Frame) -> RuntimeError {
+        self.trace.push(frame);
+        self
+    }
+
+    pub fn type_mismatch(operation: &str, expected: &str, found: &str, span: Span) -> RuntimeError {
+        RuntimeError::new(RuntimeErrorKind::TypeMismatch {
+            operation: operation.to_string(),
+            expected: expected.to_string(),
+            found: found.to_string()
+        }, span)
+    }
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            RuntimeErrorKind::TypeMismatch { ref operation, ref expected, ref found } =>
+                write!(f, "mismatched types for {}: expected {}, found {}", operation, expected, found),
+            RuntimeErrorKind::UndefinedVariable(ref name) =>
+                write!(f, "undefined variable `{}`", name),
+            RuntimeErrorKind::UndeclaredVariable(ref name) =>
+                write!(f, "cannot assign to undeclared variable `{}`", name),
+            RuntimeErrorKind::ArityMismatch { expected, found, .. } =>
+                write!(
+                    f,
+                    "function takes {} argument{} but {} {} given",
+                    expected,
+                    if expected == 1 { "" } else { "s" },
+                    found,
+                    if found == 1 { "was" } else { "were" }
+                ),
+            RuntimeErrorKind::NotCallable(ref type_name) =>
+                write!(f, "cannot call a value of type {}", type_name),
+            RuntimeErrorKind::IndexOutOfRange { ref index, length } =>
+                write!(f, "index {} is out of range for a list of length {}", index, length),
+            RuntimeErrorKind::Overflow(ref operator) =>
+                write!(f, "the result of `{}` is too large", operator),
+            RuntimeErrorKind::MissingKey(ref key) =>
+                write!(f, "map has no key {:?}", key),
+            RuntimeErrorKind::DivisionByZero =>
+                write!(f, "division by zero"),
+            RuntimeErrorKind::UnsupportedOperator(ref operator) =>
+                write!(f, "operator `{}` is not supported", operator),
+            RuntimeErrorKind::Misplaced(Keyword::Return) =>
+                write!(f, "`return` outside of a function"),
+            RuntimeErrorKind::Misplaced(ref keyword) =>
+                write!(f, "`{}` outside of a loop", keyword),
+            RuntimeErrorKind::InvalidAssignment =>
+                write!(f, "invalid assignment target"),
+            RuntimeErrorKind::InvalidSyntax =>
+                write!(f, "cannot evaluate invalid syntax")
+        }
+    }
+}
@@ -1,295 +1,805 @@
-use super::{ AST, Environment };
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::rc::Rc;
 
-pub fn evaluate(ast: AST, env: &mut Environment) -> Option<AST> {
-    match ast {
-        AST::Number(_) | AST::String_(_) | AST::Boolean(_) => Some(ast),
-        AST::Variable(name) => Some(env.get(&name)),
-        AST::Program(exprs) => {
-            for expr in exprs.iter() {
-                evaluate(expr.clone(), env);
+use super::{ AST, ASTKind, Keyword, Span, Environment, Value, List, Map, Function, RuntimeError, RuntimeErrorKind, Frame, BigInt, Rational };
+
+/// Something that stops evaluation early. Everything but errors is caught by
+/// the enclosing loop or function call, the spans are of the `break`,
+/// `continue` or `return` in case there isn't one.
+enum Unwind {
+    Error(RuntimeError),
+    Break(Span),
+    Continue(Span),
+    Return(Value, Span)
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Unwind {
+        Unwind::Error(error)
+    }
+}
+
+pub fn evaluate(ast: &AST, env: &Environment) -> Result<Value, RuntimeError> {
+    match eval(ast, env) {
+        Ok(value) => Ok(value),
+        Err(Unwind::Error(error)) => Err(error),
+        // the parser reports these, but they are still in the tree it
+        // returns from `parse_partial`
+        Err(Unwind::Break(span)) => Err(RuntimeError::new(RuntimeErrorKind::Misplaced(Keyword::Break), span)),
+        Err(Unwind::Continue(span)) => Err(RuntimeError::new(RuntimeErrorKind::Misplaced(Keyword::Continue), span)),
+        Err(Unwind::Return(_, span)) => Err(RuntimeError::new(RuntimeErrorKind::Misplaced(Keyword::Return), span))
+    }
+}
+
+/// Runs a loop body once, returning whether the loop should go on.
+fn iterate(body: &AST, env: &Environment) -> Result<bool, Unwind> {
+    match eval(body, env) {
+        Ok(_) | Err(Unwind::Continue(_)) => Ok(true),
+        Err(Unwind::Break(_)) => Ok(false),
+        Err(unwind) => Err(unwind)
+    }
+}
+
+fn eval(ast: &AST, env: &Environment) -> Result<Value, Unwind> {
+    let span = ast.span;
+    match ast.kind {
+        ASTKind::Integer(n) => Ok(Value::Integer(n)),
+        ASTKind::BigInteger(ref n) => Ok(Value::BigInt(n.clone())),
+        ASTKind::Float(n) => Ok(Value::Float(n)),
+        ASTKind::String_(ref s) => Ok(Value::String_(s.clone())),
+        ASTKind::Interpolation(ref parts) => {
+            let mut string = String::new();
+            for part in parts.iter() {
+                string.push_str(&eval(part, &env.extend())?.to_string());
             }
-            None
+            Ok(Value::String_(string))
         },
-        AST::Assign { left, right, .. } => {
-            if let AST::Variable(name) = (*left).clone() {
-                if let Some(result) = evaluate(*right.clone(), &mut Environment::new(Some(env))) {
-                    env.def(&name, result);
-                }
-                None
+        ASTKind::Boolean(b) => Ok(Value::Boolean(b)),
+        ASTKind::Variable(ref name) => match env.get(name) {
+            Some(value) => Ok(value),
+            None => Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::UndefinedVariable(name.clone()), span)))
+        },
+        ASTKind::List(ref elements) => {
+            let mut values = Vec::new();
+            for element in elements.iter() {
+                values.push(eval(element, &env.extend())?);
             }
-            else {
-                env.dump();
-                panic!(format!("Can only assign to variable: {:?} = {:?}", left, right));
+            Ok(Value::list(values))
+        },
+        ASTKind::Map(ref entries) => {
+            let mut map = Map::default();
+            for (key, value) in entries.iter() {
+                map.insert(key.clone(), eval(value, &env.extend())?);
             }
+            Ok(Value::map(map))
         },
-        func @ AST::Function { .. } => {
-            Some(func)
+        ASTKind::Index { .. } | ASTKind::Field { .. } => Ok(place(ast, env)?.get(span)?),
+        ASTKind::While { ref condition, ref body } => {
+            loop {
+                match eval(condition, &env.extend())? {
+                    Value::Boolean(true) if iterate(body, &env.extend())? => {},
+                    Value::Boolean(_) => return Ok(Value::Nil),
+                    value => return Err(Unwind::Error(RuntimeError::type_mismatch("`while` condition", "boolean", value.type_name(), condition.span)))
+                };
+            }
         },
-        AST::Call { function, arguments } => {
-            if let AST::Variable(name) = *function.clone() {
-                if let function @ AST::Function { .. } = env.get(&name) {
-                    if let AST::Function { parameters, body, native } = function {
-                        if let Some(f) = native {
-                            let mut args: Vec<AST> = Vec::new();
-                            for arg in arguments.iter() {
-                                if let Some(result) = evaluate(arg.clone(), env) {
-                                    args.push(result);
-                                }
-                            }
-                            Some(f(args))
+        ASTKind::For { ref variable, ref iterable, ref body } => {
+            let run = |value: Value| {
+                let scope = env.extend();
+                scope.def(variable, value);
+                iterate(body, &scope)
+            };
+            // lists and maps are copied first, so changing them in the loop
+            // doesn't change what is looped over
+            match eval(iterable, &env.extend())? {
+                Value::List(list) => {
+                    let elements = list.borrow().clone();
+                    for element in elements {
+                        if !run(element)? {
+                            break;
                         }
-                        else {
-                            let mut fnenv = Environment::new(Some(&env));
-                            for i in 0..parameters.len() {
-                                let name = &parameters[i];
-                                let value = if let Some(value) = arguments.get(i) {
-                                    if let Some(v) = evaluate(value.clone(), &mut Environment::new(Some(env))) {
-                                        v
-                                    }
-                                    else {
-                                        AST::Boolean(false)
-                                    }
-                                }
-                                else {
-                                    AST::Boolean(false)
-                                };
-                                fnenv.def(name, value);
-                            }
-                            evaluate((*body).clone(), &mut fnenv)
+                    }
+                },
+                Value::Map(map) => {
+                    let keys: Vec<String> = map.borrow().iter().map(|(key, _)| key.clone()).collect();
+                    for key in keys {
+                        if !run(Value::String_(key))? {
+                            break;
                         }
                     }
-                    else {
-                        env.dump();
-                        panic!(format!("Cannot call non-function '{:?}'", function));
+                },
+                Value::String_(string) => {
+                    for c in string.chars() {
+                        if !run(Value::String_(c.to_string()))? {
+                            break;
+                        }
                     }
+                },
+                Value::Range(start, end) => {
+                    for i in start..end {
+                        if !run(Value::Integer(i))? {
+                            break;
+                        }
+                    }
+                },
+                value => return Err(Unwind::Error(RuntimeError::type_mismatch("`for` loop", "list, map, string or range", value.type_name(), iterable.span)))
+            }
+            Ok(Value::Nil)
+        },
+        ASTKind::Break => Err(Unwind::Break(span)),
+        ASTKind::Continue => Err(Unwind::Continue(span)),
+        ASTKind::Return(ref value) => match *value {
+            Some(ref value) => Err(Unwind::Return(eval(value, &env.extend())?, span)),
+            None => Err(Unwind::Return(Value::Nil, span))
+        },
+        ASTKind::Program(ref exprs) => {
+            let mut result = Value::Nil;
+            for expr in exprs.iter() {
+                result = eval(expr, env)?;
+            }
+            Ok(result)
+        },
+        ASTKind::Assign { ref operator, ref left, ref right, .. } => match left.kind {
+            ASTKind::Variable(ref name) => {
+                let result = eval(right, &env.extend())?;
+                if operator == ":=" {
+                    env.def(name, result.clone());
                 }
-                else {
-                    env.dump();
-                    panic!(format!("Cannot call non-function '{:?}'", function));
+                else if !env.set(name, result.clone()) {
+                    return Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::UndeclaredVariable(name.clone()), left.span)));
                 }
+                Ok(result)
+            },
+            ASTKind::Index { .. } | ASTKind::Field { .. } if operator == "=" => {
+                let place = place(left, env)?;
+                let result = eval(right, &env.extend())?;
+                place.set(result.clone());
+                Ok(result)
+            },
+            _ => Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::InvalidAssignment, left.span)))
+        },
+        ASTKind::Let { ref variables, ref body } => {
+            let scope = env.extend();
+            for (name, value) in variables.iter() {
+                let value = eval(value, &scope)?;
+                scope.def(name, value);
             }
-            else {
-                panic!(format!("Unrecognized token: {:?}", function));
+            eval(body, &scope)
+        },
+        ASTKind::Function { ref parameters, ref body, .. } => {
+            Ok(Value::Function(Rc::new(Function {
+                parameters: parameters.clone(),
+                body: (**body).clone(),
+                environment: env.clone(),
+                span
+            })))
+        },
+        ASTKind::Call { ref function, ref arguments } => {
+            let name = match function.kind {
+                ASTKind::Variable(ref name) | ASTKind::Field { ref name, .. } => name.clone(),
+                _ => "<anonymous>".to_string()
+            };
+            match eval(function, env)? {
+                Value::Native(ref native) => {
+                    let mut args: Vec<Value> = Vec::new();
+                    for arg in arguments.iter() {
+                        args.push(eval(arg, env)?);
+                    }
+                    Ok(native.call(args, span)?)
+                },
+                Value::Function(ref function) => {
+                    if function.parameters.len() != arguments.len() {
+                        return Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::ArityMismatch {
+                            expected: function.parameters.len(),
+                            found: arguments.len(),
+                            definition: function.span
+                        }, span)));
+                    }
+                    let fnenv = function.environment.extend();
+                    let mut summaries = Vec::new();
+                    for (parameter, value) in function.parameters.iter().zip(arguments) {
+                        let value = eval(value, &env.extend())?;
+                        summaries.push(format!("{} = {}", parameter, value.summary()));
+                        fnenv.def(parameter, value);
+                    }
+                    let frame = Frame {
+                        function: name,
+                        call_site: span,
+                        arguments: summaries.join(", ")
+                    };
+                    match eval(&function.body, &fnenv) {
+                        Err(Unwind::Return(value, _)) => Ok(value),
+                        Err(Unwind::Error(error)) => Err(Unwind::Error(error.unwind(frame))),
+                        result => result
+                    }
+                },
+                ref value => Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::NotCallable(value.type_name().to_string()), function.span)))
             }
         },
-        AST::If { condition, then, otherwise } => {
-            let cond = evaluate(*condition, &mut Environment::new(Some(env)));
+        ASTKind::If { ref condition, ref then, ref otherwise } => {
+            let cond = eval(condition, &env.extend())?;
             match cond {
-                Some(AST::Boolean(b)) => {
+                Value::Boolean(b) => {
                     if b {
-                        evaluate(*then, &mut Environment::new(Some(env)))
+                        eval(then, &env.extend())
                     }
-                    else if let Some(exp) = otherwise {
-                        evaluate(*exp, &mut Environment::new(Some(env)))
+                    else if let Some(ref exp) = *otherwise {
+                        eval(exp, &env.extend())
                     }
                     else {
-                        Some(AST::Boolean(false))
+                        Ok(Value::Nil)
                     }
                 },
-                _ => {
-                    env.dump();
-                    panic!("Condition must evaluate to boolean");
-                }
+                _ => Err(Unwind::Error(RuntimeError::type_mismatch("`if` condition", "boolean", cond.type_name(), condition.span)))
             }
         },
-        AST::Binary { operator, left, right } => {
-            if let Some(left) = evaluate(*left, &mut Environment::new(Some(env))) {
-                if let Some(right) = evaluate(*right, &mut Environment::new(Some(env))) {
-                    match operator.as_ref() {
-                        "+"  => Some(add(left, right, &env)),
-                        "-"  => Some(subtract(left, right, &env)),
-                        "*"  => Some(multiply(left, right, &env)),
-                        "/"  => Some(divide(left, right, &env)),
-                        "%"  => Some(modulus(left, right, &env)),
-                        "||" => Some(or(left, right, &env)),
-                        "&&" => Some(and(left, right, &env)),
-                        "==" => Some(equals(left, right, &env)),
-                        "<"  => Some(less_than(left, right, &env)),
-                        ">"  => Some(greater_than(left,right, &env)),
-                        "<=" => Some(less_than_or_equals(left, right, &env)),
-                        ">=" => Some(greater_than_or_equals(left, right, &env)),
-                        _ => {
-                            env.dump();
-                            panic!(format!("Unkown operator '{:?}'", operator));
-                        }
-                    }
-                }
-                else {
-                    env.dump();
-                    panic!("Could not evaluate right operand");
-                }
+        ASTKind::Error => {
+            Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::InvalidSyntax, span)))
+        },
+        ASTKind::Binary { ref operator, ref left, ref right } => {
+            let left = eval(left, &env.extend())?;
+            // `&&` and `||` only evaluate the right hand side if it can
+            // change the result
+            match (operator.as_ref(), &left) {
+                ("&&", &Value::Boolean(false)) => return Ok(Value::Boolean(false)),
+                ("||", &Value::Boolean(true)) => return Ok(Value::Boolean(true)),
+                _ => {}
+            }
+            let right = eval(right, &env.extend())?;
+            match binary_operator(operator) {
+                Some(apply) => Ok(apply(left, right, span)?),
+                None => Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::UnsupportedOperator(operator.clone()), span)))
             }
-            else {
-                env.dump();
-                panic!("Unable to evaluate left operand");
+        },
+        ASTKind::Unary { ref operator, ref operand } => {
+            let value = eval(operand, &env.extend())?;
+            match (operator.as_ref(), &value) {
+                ("-", &Value::Integer(n)) => Ok(n.checked_neg().map(Value::Integer).unwrap_or_else(|| Value::from_bigint(-&BigInt::from_i64(n)))),
+                ("-", Value::BigInt(n)) => Ok(Value::from_bigint(-n)),
+                ("-", Value::Rational(r)) => Ok(Value::Rational(-r)),
+                ("-", &Value::Float(n)) => Ok(Value::Float(-n)),
+                ("+", &Value::Integer(_)) | ("+", &Value::BigInt(_)) | ("+", &Value::Rational(_)) | ("+", &Value::Float(_)) => Ok(value),
+                ("!", &Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+                ("-", _) | ("+", _) =>
+                    Err(Unwind::Error(RuntimeError::type_mismatch(&format!("`{}`", operator), "number", value.type_name(), operand.span))),
+                ("!", _) =>
+                    Err(Unwind::Error(RuntimeError::type_mismatch("`!`", "boolean", value.type_name(), operand.span))),
+                _ => Err(Unwind::Error(RuntimeError::new(RuntimeErrorKind::UnsupportedOperator(operator.clone()), span)))
             }
         }
     }
 }
 
-fn lookup_or_self(ast: &AST, env: &Environment) -> AST {
-    if let AST::Variable(name) = ast {
-        env.get(&name)
+/// An element of a list or an entry of a map, which can be read or assigned
+/// to. Map entries might not exist yet.
+enum Place {
+    Element(List, usize),
+    Entry(Rc<RefCell<Map>>, String)
+}
+
+impl Place {
+    /// The value in the place, `span` is used if the map has no such entry.
+    fn get(&self, span: Span) -> Result<Value, RuntimeError> {
+        match *self {
+            Place::Element(ref list, i) => Ok(list.borrow()[i].clone()),
+            Place::Entry(ref map, ref key) => match map.borrow().get(key) {
+                Some(value) => Ok(value.clone()),
+                None => Err(RuntimeError::new(RuntimeErrorKind::MissingKey(key.clone()), span))
+            }
+        }
     }
-    else {
-        ast.clone()
+
+    fn set(&self, value: Value) {
+        match *self {
+            Place::Element(ref list, i) => list.borrow_mut()[i] = value,
+            Place::Entry(ref map, ref key) => map.borrow_mut().insert(key.clone(), value)
+        }
     }
 }
 
-fn add(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
-    match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Number(l + r),
-        (AST::String_(l), AST::String_(r)) => AST::String_([l.as_ref(), r.as_ref()].join("")),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot add operands: {:?} + {:?}", left, right))
-        }
+/// Evaluates an `Index` or `Field` node as far as finding the place it
+/// refers to. List indices are checked to be in range.
+fn place(ast: &AST, env: &Environment) -> Result<Place, Unwind> {
+    match ast.kind {
+        ASTKind::Field { ref object, ref name } => match eval(object, &env.extend())? {
+            Value::Map(map) => Ok(Place::Entry(map, name.clone())),
+            value => Err(Unwind::Error(RuntimeError::type_mismatch("field access", "map", value.type_name(), object.span)))
+        },
+        ASTKind::Index { ref object, ref index } => match eval(object, &env.extend())? {
+            Value::List(list) => {
+                let i = match eval(index, &env.extend())? {
+                    Value::Integer(i) => i,
+                    Value::BigInt(i) => return Err(Unwind::Error(out_of_range(i.to_string(), list.borrow().len(), index.span))),
+                    value => return Err(Unwind::Error(RuntimeError::type_mismatch("list index", "integer", value.type_name(), index.span)))
+                };
+                let length = list.borrow().len();
+                match usize::try_from(i) {
+                    Ok(i) if i < length => Ok(Place::Element(list, i)),
+                    _ => Err(Unwind::Error(out_of_range(i.to_string(), length, index.span)))
+                }
+            },
+            Value::Map(map) => match eval(index, &env.extend())? {
+                Value::String_(key) => Ok(Place::Entry(map, key)),
+                value => Err(Unwind::Error(RuntimeError::type_mismatch("map key", "string", value.type_name(), index.span)))
+            },
+            value => Err(Unwind::Error(RuntimeError::type_mismatch("indexing", "list or map", value.type_name(), object.span)))
+        },
+        _ => unreachable!("only `Index` and `Field` nodes are places")
     }
 }
 
-fn subtract(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
-    match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Number(l - r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot subtract operands: {:?} - {:?}", left, right))
-        }
+fn out_of_range(index: String, length: usize, span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::IndexOutOfRange { index, length }, span)
+}
+
+/// The longest string, in bytes, that repeating a string with `*` can make.
+const MAX_STRING_LENGTH: usize = 1 << 30;
+
+type BinaryOperator = fn(Value, Value, Span) -> Result<Value, RuntimeError>;
+
+/// The function implementing each binary operator. The operands of `&&` and
+/// `||` are only passed in when both are needed.
+fn binary_operator(operator: &str) -> Option<BinaryOperator> {
+    let apply: BinaryOperator = match operator {
+        "+"  => add,
+        "-"  => subtract,
+        "*"  => multiply,
+        "/"  => divide,
+        "//" => floor_divide,
+        "%"  => modulus,
+        "**" => power,
+        "||" => or,
+        "&&" => and,
+        "==" => equals,
+        "!=" => not_equals,
+        "<"  => less_than,
+        ">"  => greater_than,
+        "<=" => less_than_or_equals,
+        ">=" => greater_than_or_equals,
+        "&"  => bitwise_and,
+        "|"  => bitwise_or,
+        "^"  => bitwise_xor,
+        "<<" => shift_left,
+        ">>" => shift_right,
+        ".." => range,
+        _ => return None
+    };
+    Some(apply)
+}
+
+/// The error for a binary operator that cannot be applied to its operands.
+fn mismatch(operator: &str, expected: &str, left: &Value, right: &Value, span: Span) -> RuntimeError {
+    let found = format!("{} and {}", left.type_name(), right.type_name());
+    RuntimeError::type_mismatch(&format!("`{}`", operator), expected, &found, span)
+}
+
+/// The most bits an integer result of `**` or `<<` can have. Anything larger
+/// would take minutes or hours to compute, so it is an `Overflow` instead.
+const MAX_BITS: u64 = 1 << 18;
+
+/// Whether `base ** exponent` would have more than `MAX_BITS` bits. A base
+/// of n bits is at least `2 ** (n - 1)`, so this never rejects a power that
+/// would fit.
+fn power_too_large(base: &BigInt, exponent: u64) -> bool {
+    base.approximate_bits().saturating_sub(1).saturating_mul(exponent) > MAX_BITS
+}
+
+fn overflow(operator: &str, span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::Overflow(operator.to_string()), span)
+}
+
+fn division_by_zero(span: Span) -> RuntimeError {
+    RuntimeError::new(RuntimeErrorKind::DivisionByZero, span)
+}
+
+fn bigint(value: &Value) -> Option<BigInt> {
+    match *value {
+        Value::Integer(n) => Some(BigInt::from_i64(n)),
+        Value::BigInt(ref n) => Some(n.clone()),
+        _ => None
     }
 }
 
-fn multiply(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
-    match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Number(l * r),
-        (AST::String_(l), AST::Number(r)) => AST::String_(l.repeat(*r as usize)),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot multiply operands: {:?} * {:?}", left, right))
-        }
+/// Both operands as big integers, if they are both integers.
+fn bigints(left: &Value, right: &Value) -> Option<(BigInt, BigInt)> {
+    Some((bigint(left)?, bigint(right)?))
+}
+
+/// Both operands as fractions, if at least one is a rational and the other
+/// is a rational or an integer.
+fn rationals(left: &Value, right: &Value) -> Option<(Rational, Rational)> {
+    if !matches!(left, Value::Rational(_)) && !matches!(right, Value::Rational(_)) {
+        return None;
     }
+    let rational = |value: &Value| match *value {
+        Value::Rational(ref r) => Some(r.clone()),
+        _ => bigint(value).map(Rational::from_integer)
+    };
+    Some((rational(left)?, rational(right)?))
 }
 
-fn divide(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
-    match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Number(l / r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot divide operands: {:?} / {:?}", left, right))
+/// Both operands as floats, if they are numbers. An operation mixing a float
+/// with any other kind of number is done on floats.
+fn floats(left: &Value, right: &Value) -> Option<(f64, f64)> {
+    let float = |value: &Value| match *value {
+        Value::Integer(n) => Some(n as f64),
+        Value::BigInt(ref n) => Some(n.to_f64()),
+        Value::Rational(ref r) => Some(r.to_f64()),
+        Value::Float(n) => Some(n),
+        _ => None
+    };
+    Some((float(left)?, float(right)?))
+}
+
+/// An operation that is exact on integers and rationals. It is done on
+/// `i64`s when both operands are, falling back to big integers if that
+/// overflows, and on fractions if either operand is a rational.
+fn exact(left: &Value, right: &Value,
+         small: fn(i64, i64) -> Option<i64>,
+         big: fn(&BigInt, &BigInt) -> BigInt,
+         fraction: fn(&Rational, &Rational) -> Rational) -> Option<Value> {
+    if let (&Value::Integer(l), &Value::Integer(r)) = (left, right) {
+        if let Some(n) = small(l, r) {
+            return Some(Value::Integer(n));
         }
     }
+    if let Some((l, r)) = rationals(left, right) {
+        return Some(Value::from_rational(fraction(&l, &r)));
+    }
+    bigints(left, right).map(|(l, r)| Value::from_bigint(big(&l, &r)))
 }
 
-fn modulus(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
+fn add(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    if let Some(result) = exact(&left, &right, i64::checked_add, |l, r| l + r, |l, r| l + r) {
+        return Ok(result);
+    }
     match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Number(l % r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot modulus operands: {:?} % {:?}", left, right))
+        (Value::String_(l), Value::String_(r)) => Ok(Value::String_([l.as_ref(), r.as_ref()].join(""))),
+        (Value::List(l), Value::List(r)) => {
+            let mut elements = l.borrow().clone();
+            elements.extend(r.borrow().iter().cloned());
+            Ok(Value::list(elements))
+        },
+        _ => match floats(&left, &right) {
+            Some((l, r)) => Ok(Value::Float(l + r)),
+            None => Err(mismatch("+", "numbers, strings or lists", &left, &right, span))
         }
     }
 }
 
-fn or(left: AST, right: AST, env: &Environment) -> AST {
-    if let AST::Boolean(b) = lookup_or_self(&left, env) {
-        if !b {
-            if let AST::Boolean(b2) = lookup_or_self(&right, env) {
-                AST::Boolean(b || b2)
-            }
-            else {
-                env.dump();
-                panic!(format!("Cannot OR operands: {:?} || {:?}", left, right));
+fn subtract(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    if let Some(result) = exact(&left, &right, i64::checked_sub, |l, r| l - r, |l, r| l - r) {
+        return Ok(result);
+    }
+    match floats(&left, &right) {
+        Some((l, r)) => Ok(Value::Float(l - r)),
+        None => Err(mismatch("-", "numbers", &left, &right, span))
+    }
+}
+
+fn multiply(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    if let Some(result) = exact(&left, &right, i64::checked_mul, |l, r| l * r, |l, r| l * r) {
+        return Ok(result);
+    }
+    match (&left, &right) {
+        (Value::String_(l), &Value::Integer(r)) if r >= 0 => {
+            let count = usize::try_from(r).ok().filter(|&r| l.len().checked_mul(r).is_some_and(|length| length <= MAX_STRING_LENGTH));
+            match count {
+                Some(count) => Ok(Value::String_(l.repeat(count))),
+                None => Err(overflow("*", span))
             }
-        }
-        else {
-            AST::Boolean(true)
+        },
+        (Value::String_(_), Value::BigInt(r)) if !r.is_negative() => Err(overflow("*", span)),
+        _ => match floats(&left, &right) {
+            Some((l, r)) => Ok(Value::Float(l * r)),
+            None => Err(mismatch("*", "numbers, or a string and a non-negative integer", &left, &right, span))
         }
     }
-    else {
-        env.dump();
-        panic!(format!("Cannot OR operands: {:?} || {:?}", left, right));
+}
+
+/// Division of integers gives a float, use `//` for integer division. Only
+/// division involving a rational is exact.
+fn divide(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    if let Some((l, r)) = rationals(&left, &right) {
+        return l.checked_div(&r).map(Value::from_rational).ok_or_else(|| division_by_zero(span));
+    }
+    match floats(&left, &right) {
+        Some((l, r)) => Ok(Value::Float(l / r)),
+        None => Err(mismatch("/", "numbers", &left, &right, span))
+    }
+}
+
+/// Integer division rounds down, so `a == (a // b) * b + a % b` always holds.
+fn floor_divide(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    if let Some((l, r)) = rationals(&left, &right) {
+        let quotient = l.checked_div(&r).ok_or_else(|| division_by_zero(span))?;
+        return Ok(Value::from_bigint(quotient.floor()));
+    }
+    if let Some((l, r)) = bigints(&left, &right) {
+        return l.div_rem_floor(&r)
+            .map(|(quotient, _)| Value::from_bigint(quotient))
+            .ok_or_else(|| division_by_zero(span));
+    }
+    match floats(&left, &right) {
+        Some((l, r)) => Ok(Value::Float((l / r).floor())),
+        None => Err(mismatch("//", "numbers", &left, &right, span))
+    }
+}
+
+/// The remainder has the same sign as the divisor, to go with `//`.
+fn modulus(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    if let Some((l, r)) = rationals(&left, &right) {
+        let quotient = l.checked_div(&r).ok_or_else(|| division_by_zero(span))?;
+        let floored = Rational::from_integer(quotient.floor());
+        return Ok(Value::from_rational(&l - &(&r * &floored)));
+    }
+    if let Some((l, r)) = bigints(&left, &right) {
+        return l.div_rem_floor(&r)
+            .map(|(_, remainder)| Value::from_bigint(remainder))
+            .ok_or_else(|| division_by_zero(span));
+    }
+    match floats(&left, &right) {
+        Some((l, r)) => Ok(Value::Float(l - r * (l / r).floor())),
+        None => Err(mismatch("%", "numbers", &left, &right, span))
     }
 }
 
-fn and(left: AST, right: AST, env: &Environment) -> AST {
-    if let AST::Boolean(b) = lookup_or_self(&left, env) {
-        if b {
-            if let AST::Boolean(b2) = lookup_or_self(&right, env) {
-                AST::Boolean(b || b2)
+/// An integer to a non-negative integer power is an integer, and a rational
+/// to any integer power is a rational. Anything else is a float.
+fn power(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (&left, &right) {
+        // 0, 1 and -1 stay small however large the exponent is
+        (&Value::Integer(l @ -1..=1), &Value::Integer(r)) if r >= 0 =>
+            Ok(Value::Integer(if r == 0 { 1 } else if r % 2 == 0 { l * l } else { l })),
+        (&Value::Integer(l), &Value::Integer(r)) if r >= 0 => {
+            let exponent = u32::try_from(r).map_err(|_| overflow("**", span))?;
+            if let Some(n) = l.checked_pow(exponent) {
+                return Ok(Value::Integer(n));
             }
-            else {
-                env.dump();
-                panic!(format!("Cannot AND operands: {:?} && {:?}", left, right));
+            let l = BigInt::from_i64(l);
+            if power_too_large(&l, r as u64) {
+                return Err(overflow("**", span));
             }
-        }
-        else {
-            AST::Boolean(false)
+            Ok(Value::from_bigint(l.pow(exponent)))
+        },
+        (Value::BigInt(l), &Value::Integer(r)) if r >= 0 => {
+            let exponent = u32::try_from(r).map_err(|_| overflow("**", span))?;
+            if power_too_large(l, r as u64) {
+                return Err(overflow("**", span));
+            }
+            Ok(Value::from_bigint(l.pow(exponent)))
+        },
+        (Value::Rational(l), &Value::Integer(r)) => {
+            let exponent = r.unsigned_abs();
+            if power_too_large(l.numerator(), exponent) || power_too_large(l.denominator(), exponent) {
+                return Err(overflow("**", span));
+            }
+            match l.pow(r) {
+                Some(result) => Ok(Value::from_rational(result)),
+                None => Err(overflow("**", span))
+            }
+        },
+        _ => match floats(&left, &right) {
+            Some((l, r)) => Ok(Value::Float(l.powf(r))),
+            None => Err(mismatch("**", "numbers", &left, &right, span))
         }
     }
-    else {
-        env.dump();
-        panic!(format!("Cannot AND operands: {:?} && {:?}", left, right));
+}
+
+fn or(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (&left, &right) {
+        (&Value::Boolean(l), &Value::Boolean(r)) => Ok(Value::Boolean(l || r)),
+        _ => Err(mismatch("||", "booleans", &left, &right, span))
+    }
+}
+
+fn and(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (&left, &right) {
+        (&Value::Boolean(l), &Value::Boolean(r)) => Ok(Value::Boolean(l && r)),
+        _ => Err(mismatch("&&", "booleans", &left, &right, span))
+    }
+}
+
+fn equals(left: Value, right: Value, _: Span) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(left == right))
+}
+
+fn not_equals(left: Value, right: Value, _: Span) -> Result<Value, RuntimeError> {
+    Ok(Value::Boolean(left != right))
+}
+
+/// Compares two numbers, exactly unless one of them is a float. The result
+/// is false if either is NaN.
+fn comparison(operator: &str, left: Value, right: Value, span: Span, test: fn(Ordering) -> bool) -> Result<Value, RuntimeError> {
+    let ordering = if let (&Value::Integer(l), &Value::Integer(r)) = (&left, &right) {
+        Some(l.cmp(&r))
+    }
+    else if let Some((l, r)) = rationals(&left, &right) {
+        Some(l.cmp(&r))
+    }
+    else if let Some((l, r)) = bigints(&left, &right) {
+        Some(l.cmp(&r))
     }
+    else {
+        match floats(&left, &right) {
+            Some((l, r)) => l.partial_cmp(&r),
+            None => return Err(mismatch(operator, "numbers", &left, &right, span))
+        }
+    };
+    Ok(Value::Boolean(ordering.is_some_and(test)))
+}
+
+fn less_than(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    comparison("<", left, right, span, Ordering::is_lt)
+}
+
+fn less_than_or_equals(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    comparison("<=", left, right, span, Ordering::is_le)
+}
+
+fn greater_than(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    comparison(">", left, right, span, Ordering::is_gt)
 }
 
-fn equals(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
-    AST::Boolean(left == right)
+fn greater_than_or_equals(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    comparison(">=", left, right, span, Ordering::is_ge)
 }
 
-fn less_than(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
+fn bitwise_and(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
     match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Boolean(l < r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot compare operands: {:?} < {:?}", left, right))
+        (&Value::Integer(l), &Value::Integer(r)) => Ok(Value::Integer(l & r)),
+        _ => match bigints(&left, &right) {
+            Some((l, r)) => Ok(Value::from_bigint(&l & &r)),
+            None => Err(mismatch("&", "integers", &left, &right, span))
         }
     }
 }
 
-fn less_than_or_equals(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
+fn bitwise_or(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
     match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Boolean(l <= r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot compare operands: {:?} <= {:?}", left, right))
+        (&Value::Integer(l), &Value::Integer(r)) => Ok(Value::Integer(l | r)),
+        _ => match bigints(&left, &right) {
+            Some((l, r)) => Ok(Value::from_bigint(&l | &r)),
+            None => Err(mismatch("|", "integers", &left, &right, span))
         }
     }
 }
 
-fn greater_than(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
+fn bitwise_xor(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
     match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Boolean(l > r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot compare operands: {:?} > {:?}", left, right))
+        (&Value::Integer(l), &Value::Integer(r)) => Ok(Value::Integer(l ^ r)),
+        _ => match bigints(&left, &right) {
+            Some((l, r)) => Ok(Value::from_bigint(&l ^ &r)),
+            None => Err(mismatch("^", "integers", &left, &right, span))
         }
     }
 }
 
-fn greater_than_or_equals(left: AST, right: AST, env: &Environment) -> AST {
-    let left = lookup_or_self(&left, env);
-    let right = lookup_or_self(&right, env);
+/// Shifts work on integers of any size, shifting left by `n` is the same as
+/// multiplying by `2 ** n` and shifting right rounds down.
+fn shift_left(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
     match (&left, &right) {
-        (AST::Number(l), AST::Number(r)) => AST::Boolean(l >= r),
-        _ => {
-            env.dump();
-            panic!(format!("Cannot compare operands: {:?} >= {:?}", left, right))
-        }
+        (&Value::Integer(l), &Value::Integer(r @ 0..=63)) if (l << r) >> r == l => Ok(Value::Integer(l << r)),
+        (_, &Value::Integer(r)) if r >= 0 => {
+            let l = bigint(&left).ok_or_else(|| mismatch("<<", "an integer and a non-negative shift", &left, &right, span))?;
+            if l.is_zero() {
+                return Ok(Value::Integer(0));
+            }
+            if l.approximate_bits().saturating_add(r as u64) > MAX_BITS {
+                return Err(overflow("<<", span));
+            }
+            let shift = u32::try_from(r).map_err(|_| overflow("<<", span))?;
+            Ok(Value::from_bigint(&l * &BigInt::from_i64(2).pow(shift)))
+        },
+        _ => Err(mismatch("<<", "an integer and a non-negative shift", &left, &right, span))
+    }
+}
+
+fn shift_right(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (&left, &right) {
+        (&Value::Integer(l), &Value::Integer(r)) if r >= 0 => Ok(Value::Integer(l >> r.min(63))),
+        // shifting out every bit leaves 0, or -1 for negative numbers
+        (Value::BigInt(l), &Value::Integer(r)) if r >= 0 && r as u64 >= l.approximate_bits() =>
+            Ok(Value::Integer(if l.is_negative() { -1 } else { 0 })),
+        (Value::BigInt(l), &Value::Integer(r)) if r >= 0 => {
+            let shift = u32::try_from(r).map_err(|_| overflow(">>", span))?;
+            let (quotient, _) = l.div_rem_floor(&BigInt::from_i64(2).pow(shift)).expect("powers of two are never zero");
+            Ok(Value::from_bigint(quotient))
+        },
+        _ => Err(mismatch(">>", "an integer and a non-negative shift", &left, &right, span))
+    }
+}
+
+fn range(left: Value, right: Value, span: Span) -> Result<Value, RuntimeError> {
+    match (&left, &right) {
+        (&Value::Integer(start), &Value::Integer(end)) => Ok(Value::Range(start, end)),
+        _ => Err(mismatch("..", "integers", &left, &right, span))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::define_builtins;
+    use parser::{ Parser, TokenStream, InputStream };
+
+    fn run(code: &str) -> Result<Value, RuntimeError> {
+        let program = Parser::new(TokenStream::new(InputStream::new(code))).parse().expect("valid syntax");
+        let env = Environment::new(None);
+        define_builtins(&env);
+        evaluate(&program, &env)
+    }
+
+    fn is_overflow(code: &str) -> bool {
+        matches!(run(code), Err(RuntimeError { kind: RuntimeErrorKind::Overflow(_), .. }))
+    }
+
+    fn is_mismatch(code: &str) -> bool {
+        matches!(run(code), Err(RuntimeError { kind: RuntimeErrorKind::TypeMismatch { .. }, .. }))
+    }
+
+    #[test]
+    fn huge_results_are_rejected_quickly() {
+        assert!(is_overflow("2 ** 4000000000"));
+        assert!(is_overflow("2 ** 5000000000"));
+        assert!(is_overflow("(2 ** 100) ** 4000000"));
+        assert!(is_overflow("1 << 4000000000"));
+        assert!(is_overflow("rational(1, 2) ** 4000000000"));
+        assert!(is_overflow("\"ab\" * 9223372036854775807"));
+        assert!(is_overflow("\"ab\" * (2 ** 70)"));
+    }
+
+    #[test]
+    fn large_powers_of_small_numbers_are_computed() {
+        assert_eq!(run("1 ** 4000000000"), Ok(Value::Integer(1)));
+        assert_eq!(run("(-1) ** 4000000001"), Ok(Value::Integer(-1)));
+        assert_eq!(run("1 ** 5000000000"), Ok(Value::Integer(1)));
+        assert_eq!(run("0 ** 5000000000"), Ok(Value::Integer(0)));
+        assert_eq!(run("(-1) ** 5000000001"), Ok(Value::Integer(-1)));
+        assert_eq!(run("(-1) ** 5000000000"), Ok(Value::Integer(1)));
+        assert_eq!(run("0 ** 0"), Ok(Value::Integer(1)));
+        assert_eq!(run("0 << 4000000000"), Ok(Value::Integer(0)));
+        assert_eq!(run("(-(2 ** 100)) >> 4000000000"), Ok(Value::Integer(-1)));
+        assert_eq!(run("2 ** 64"), Ok(Value::BigInt(BigInt::from_str_radix("18446744073709551616", 10).unwrap())));
+    }
+
+    #[test]
+    fn bitwise_operators_work_on_big_integers() {
+        assert_eq!(run("(2 ** 64) & 1"), Ok(Value::Integer(0)));
+        assert_eq!(run("(2 ** 64 + 5) & 7"), Ok(Value::Integer(5)));
+        assert_eq!(run("((2 ** 64) | 1) == 2 ** 64 + 1"), Ok(Value::Boolean(true)));
+        assert_eq!(run("(2 ** 64) ^ (2 ** 64 + 3)"), Ok(Value::Integer(3)));
+        assert_eq!(run("(-(2 ** 64)) & -1 == -(2 ** 64)"), Ok(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn negative_shifts_are_rejected() {
+        assert!(is_mismatch("8 >> -1"));
+        assert!(is_mismatch("(2 ** 100) >> -1"));
+        assert!(is_mismatch("(2 ** 100) << -1"));
+    }
+
+    #[test]
+    fn misplaced_jumps_in_a_partial_tree_are_errors() {
+        let (program, errors) = Parser::new(TokenStream::new(InputStream::new("x := 1; break"))).parse_partial();
+        assert_eq!(errors.len(), 1);
+        let result = evaluate(&program, &Environment::new(None));
+        assert_eq!(result.map_err(|error| error.kind), Err(RuntimeErrorKind::Misplaced(Keyword::Break)));
+    }
+
+    #[test]
+    fn jumps_escaping_a_sub_tree_are_errors() {
+        let program = Parser::new(TokenStream::new(InputStream::new("while true { return 1 }"))).parse_partial().0;
+        let body = match program.kind {
+            ASTKind::Program(ref statements) => match statements[0].kind {
+                ASTKind::While { ref body, .. } => body.clone(),
+                _ => panic!("expected a loop")
+            },
+            _ => panic!("expected a program")
+        };
+        let result = evaluate(&body, &Environment::new(None));
+        assert_eq!(result.map_err(|error| error.kind), Err(RuntimeErrorKind::Misplaced(Keyword::Return)));
     }
 }
@@ -0,0 +1,50 @@
+use std::collections::HashMap;
+use std::slice;
+
+use super::Value;
+
+/// A map from strings to values, which keeps its keys in the order they were
+/// first inserted.
+#[derive(Debug, Clone, Default)]
+pub struct Map {
+    entries: Vec<(String, Value)>,
+    /// The position of each key in `entries`.
+    indices: HashMap<String, usize>
+}
+
+impl Map {
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.indices.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.indices.contains_key(key)
+    }
+
+    /// Replaces the value of an existing key without moving it, or adds the
+    /// key at the end.
+    pub fn insert(&mut self, key: String, value: Value) {
+        match self.indices.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.indices.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, (String, Value)> {
+        self.entries.iter()
+    }
+}
+
+/// Maps are equal if they have the same entries, in any order.
+impl PartialEq for Map {
+    fn eq(&self, other: &Map) -> bool {
+        self.len() == other.len() && self.iter().all(|(key, value)| other.get(key) == Some(value))
+    }
+}
@@ -1,7 +1,18 @@
+mod builtins;
 mod environment;
+mod error;
 mod evaluate;
+mod map;
+mod rational;
+mod value;
 
 use super::parser::*;
+use super::bigint::BigInt;
 
+pub use self::builtins::define_builtins;
 pub use self::environment::Environment;
+pub use self::error::*;
 pub use self::evaluate::evaluate;
+pub use self::map::Map;
+pub use self::rational::Rational;
+pub use self::value::*;
@@ -0,0 +1,165 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{ Add, Sub, Mul, Neg };
+
+use super::BigInt;
+
+/// An exact fraction, kept in lowest terms with a positive denominator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rational {
+    numerator: BigInt,
+    denominator: BigInt
+}
+
+impl Rational {
+    /// Returns `None` if `denominator` is zero.
+    pub fn new(numerator: BigInt, denominator: BigInt) -> Option<Rational> {
+        if denominator.is_zero() {
+            return None;
+        }
+        let mut divisor = numerator.gcd(&denominator);
+        if denominator.is_negative() {
+            divisor = -&divisor;
+        }
+        Some(Rational {
+            numerator: numerator.div_rem_floor(&divisor)?.0,
+            denominator: denominator.div_rem_floor(&divisor)?.0
+        })
+    }
+
+    pub fn from_integer(n: BigInt) -> Rational {
+        Rational {
+            numerator: n,
+            denominator: BigInt::from_i64(1)
+        }
+    }
+
+    pub fn numerator(&self) -> &BigInt {
+        &self.numerator
+    }
+
+    pub fn denominator(&self) -> &BigInt {
+        &self.denominator
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.denominator == BigInt::from_i64(1)
+    }
+
+    /// Returns `None` when dividing by zero.
+    pub fn checked_div(&self, other: &Rational) -> Option<Rational> {
+        Rational::new(&self.numerator * &other.denominator, &self.denominator * &other.numerator)
+    }
+
+    /// The largest integer not greater than the fraction.
+    pub fn floor(&self) -> BigInt {
+        self.numerator.div_rem_floor(&self.denominator).map(|(quotient, _)| quotient).unwrap_or_else(|| self.numerator.clone())
+    }
+
+    /// Raises the fraction to an integer power, returning `None` for a
+    /// negative power of zero.
+    pub fn pow(&self, exponent: i64) -> Option<Rational> {
+        let power = u32::try_from(exponent.unsigned_abs()).ok()?;
+        let (numerator, denominator) = (self.numerator.pow(power), self.denominator.pow(power));
+        if exponent < 0 {
+            Rational::new(denominator, numerator)
+        }
+        else {
+            Rational::new(numerator, denominator)
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.numerator.to_f64() / self.denominator.to_f64()
+    }
+}
+
+impl Add for &Rational {
+    type Output = Rational;
+
+    fn add(self, other: &Rational) -> Rational {
+        let numerator = &(&self.numerator * &other.denominator) + &(&other.numerator * &self.denominator);
+        Rational::new(numerator, &self.denominator * &other.denominator).expect("denominators are never zero")
+    }
+}
+
+impl Sub for &Rational {
+    type Output = Rational;
+
+    fn sub(self, other: &Rational) -> Rational {
+        self + &-other
+    }
+}
+
+impl Mul for &Rational {
+    type Output = Rational;
+
+    fn mul(self, other: &Rational) -> Rational {
+        Rational::new(&self.numerator * &other.numerator, &self.denominator * &other.denominator)
+            .expect("denominators are never zero")
+    }
+}
+
+impl Neg for &Rational {
+    type Output = Rational;
+
+    fn neg(self) -> Rational {
+        Rational {
+            numerator: -&self.numerator,
+            denominator: self.denominator.clone()
+        }
+    }
+}
+
+impl Ord for Rational {
+    fn cmp(&self, other: &Rational) -> Ordering {
+        (&self.numerator * &other.denominator).cmp(&(&other.numerator * &self.denominator))
+    }
+}
+
+impl PartialOrd for Rational {
+    fn partial_cmp(&self, other: &Rational) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rational(numerator: i64, denominator: i64) -> Option<Rational> {
+        Rational::new(BigInt::from_i64(numerator), BigInt::from_i64(denominator))
+    }
+
+    #[test]
+    fn new_moves_the_sign_to_the_numerator() {
+        let half = rational(1, -2).unwrap();
+        assert_eq!(half.numerator(), &BigInt::from_i64(-1));
+        assert_eq!(half.denominator(), &BigInt::from_i64(2));
+        assert_eq!(rational(-4, -6).unwrap().to_string(), "2/3");
+        assert_eq!(rational(4, -6).unwrap().to_string(), "-2/3");
+    }
+
+    #[test]
+    fn new_reduces_to_lowest_terms() {
+        assert_eq!(rational(6, 3), Some(Rational::from_integer(BigInt::from_i64(2))));
+        assert_eq!(rational(0, -7).unwrap().to_string(), "0/1");
+        assert_eq!(rational(1, 0), None);
+    }
+
+    #[test]
+    fn arithmetic_stays_exact() {
+        let third = rational(1, 3).unwrap();
+        assert_eq!(&(&third + &third) + &third, Rational::from_integer(BigInt::from_i64(1)));
+        assert_eq!(third.pow(-2), rational(9, 1));
+        assert_eq!(rational(-7, 2).unwrap().floor(), BigInt::from_i64(-4));
+        assert_eq!(rational(0, 1).unwrap().pow(-1), None);
+    }
+}
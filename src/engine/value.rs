@@ -0,0 +1,312 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::thread::LocalKey;
+
+use super::{ AST, Span, Environment, RuntimeError, BigInt, Rational, Map };
+
+/// A function implemented in Rust that can be called from the language. It
+/// gets the evaluated arguments and the span of the call, for errors.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    function: Rc<dyn Fn(Vec<Value>, Span) -> Result<Value, RuntimeError>>
+}
+
+impl NativeFunction {
+    pub fn new<F>(name: &str, function: F) -> NativeFunction
+        where F: Fn(Vec<Value>, Span) -> Result<Value, RuntimeError> + 'static {
+        NativeFunction {
+            name: name.to_string(),
+            function: Rc::new(function)
+        }
+    }
+
+    pub fn call(&self, arguments: Vec<Value>, span: Span) -> Result<Value, RuntimeError> {
+        (self.function)(arguments, span)
+    }
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[native code]")
+    }
+}
+
+/// A function defined in the language, closing over the environment it was
+/// defined in.
+pub struct Function {
+    pub parameters: Vec<String>,
+    pub body: AST,
+    pub environment: Environment,
+    /// Where the function was defined.
+    pub span: Span
+}
+
+impl fmt::Debug for Function {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Function")
+            .field("parameters", &self.parameters)
+            .field("span", &self.span)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The elements of a list, shared by every reference to it.
+pub type List = Rc<RefCell<Vec<Value>>>;
+
+/// The result of evaluating an expression.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Integer(i64),
+    /// An integer too large for `Integer`. Results that fit in 64 bits are
+    /// always turned back into an `Integer`.
+    BigInt(BigInt),
+    /// A fraction that isn't a whole number, whole numbers become integers.
+    Rational(Rational),
+    Float(f64),
+    String_(String),
+    /// Lists are shared, so changing an element through one reference to a
+    /// list changes it for all of them.
+    List(List),
+    /// Maps are shared like lists.
+    Map(Rc<RefCell<Map>>),
+    /// The integers from `start` up to but not including `end`.
+    Range(i64, i64),
+    Function(Rc<Function>),
+    Native(NativeFunction)
+}
+
+impl Value {
+    pub fn from_bigint(n: BigInt) -> Value {
+        match n.to_i64() {
+            Some(n) => Value::Integer(n),
+            None => Value::BigInt(n)
+        }
+    }
+
+    pub fn list(elements: Vec<Value>) -> Value {
+        Value::List(Rc::new(RefCell::new(elements)))
+    }
+
+    pub fn map(map: Map) -> Value {
+        Value::Map(Rc::new(RefCell::new(map)))
+    }
+
+    pub fn from_rational(r: Rational) -> Value {
+        if r.is_integer() {
+            Value::from_bigint(r.numerator().clone())
+        }
+        else {
+            Value::Rational(r)
+        }
+    }
+
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match *self {
+            Value::Nil => "nil",
+            Value::Boolean(_) => "boolean",
+            Value::Integer(_) | Value::BigInt(_) => "integer",
+            Value::Rational(_) => "rational",
+            Value::Float(_) => "float",
+            Value::String_(_) => "string",
+            Value::List(_) => "list",
+            Value::Map(_) => "map",
+            Value::Range(..) => "range",
+            Value::Function(_) | Value::Native(_) => "function"
+        }
+    }
+
+    /// A short description of the value, for stack traces. Long strings are
+    /// cut short, and so are lists and maps with many elements.
+    pub fn summary(&self) -> String {
+        match *self {
+            Value::String_(ref s) if s.chars().count() > 20 => format!("{:?}...", s.chars().take(20).collect::<String>()),
+            Value::String_(ref s) => format!("{:?}", s),
+            Value::List(ref elements) => guard(&PRINTING, address(elements), || {
+                let elements = elements.borrow();
+                summarize(('[', ']'), elements.iter().map(Value::summary), elements.len())
+            }).unwrap_or_else(|| "[...]".to_string()),
+            Value::Map(ref map) => guard(&PRINTING, address(map), || {
+                let map = map.borrow();
+                let entries = map.iter().map(|(key, value)| format!("{}: {}", quote_key(key), value.summary()));
+                summarize(('{', '}'), entries, map.len())
+            }).unwrap_or_else(|| "{...}".to_string()),
+            _ => self.to_string()
+        }
+    }
+}
+
+/// How many elements of a list or map are shown in its summary.
+const SUMMARY_LENGTH: usize = 5;
+
+fn summarize<I: Iterator<Item = String>>(brackets: (char, char), elements: I, length: usize) -> String {
+    let mut shown: Vec<String> = elements.take(SUMMARY_LENGTH).collect();
+    if length > SUMMARY_LENGTH {
+        shown.push("...".to_string());
+    }
+    format!("{}{}{}", brackets.0, shown.join(", "), brackets.1)
+}
+
+/// Functions are only equal to themselves, everything else is compared by
+/// value, lists element by element and maps entry by entry. Integers and
+/// floats are compared as numbers, so `1 == 1.0`.
+impl PartialEq for Value {
+    fn eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (&Value::Nil, &Value::Nil) => true,
+            (&Value::Boolean(l), &Value::Boolean(r)) => l == r,
+            (&Value::Integer(l), &Value::Integer(r)) => l == r,
+            (&Value::Float(l), &Value::Float(r)) => l == r,
+            (Value::BigInt(l), Value::BigInt(r)) => l == r,
+            (Value::Rational(l), Value::Rational(r)) => l == r,
+            (&Value::Integer(l), &Value::Float(r)) | (&Value::Float(r), &Value::Integer(l)) => l as f64 == r,
+            (Value::BigInt(l), &Value::Float(r)) | (&Value::Float(r), Value::BigInt(l)) => l.to_f64() == r,
+            (Value::Rational(l), &Value::Float(r)) | (&Value::Float(r), Value::Rational(l)) => l.to_f64() == r,
+            (Value::String_(l), Value::String_(r)) => l == r,
+            // two lists or maps that are already being compared further up
+            // are taken to be equal, so ones that contain themselves can be
+            // compared
+            (Value::List(l), Value::List(r)) =>
+                Rc::ptr_eq(l, r) || guard(&COMPARING, (address(l), address(r)), || *l.borrow() == *r.borrow()).unwrap_or(true),
+            (Value::Map(l), Value::Map(r)) =>
+                Rc::ptr_eq(l, r) || guard(&COMPARING, (address(l), address(r)), || *l.borrow() == *r.borrow()).unwrap_or(true),
+            (&Value::Range(l_start, l_end), &Value::Range(r_start, r_end)) => l_start == r_start && l_end == r_end,
+            (Value::Function(l), Value::Function(r)) => Rc::ptr_eq(l, r),
+            (Value::Native(l), Value::Native(r)) => Rc::ptr_eq(&l.function, &r.function),
+            _ => false
+        }
+    }
+}
+
+/// The standard to-string rules, used when printing values.
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Nil => write!(f, "nil"),
+            Value::Boolean(b) => write!(f, "{}", b),
+            Value::Integer(n) => write!(f, "{}", n),
+            Value::BigInt(ref n) => write!(f, "{}", n),
+            Value::Rational(ref r) => write!(f, "{}", r),
+            // floats always show a fractional part or exponent, so they can
+            // be told apart from integers
+            Value::Float(n) => write!(f, "{:?}", n),
+            Value::String_(ref s) => write!(f, "{}", s),
+            // a list or map inside itself is printed as `[...]` or `{...}`
+            Value::List(ref elements) =>
+                guard(&PRINTING, address(elements), || write_list(f, &elements.borrow())).unwrap_or_else(|| write!(f, "[...]")),
+            Value::Map(ref map) =>
+                guard(&PRINTING, address(map), || write_map(f, &map.borrow())).unwrap_or_else(|| write!(f, "{{...}}")),
+            Value::Range(start, end) => write!(f, "{}..{}", start, end),
+            Value::Function(ref function) => write!(f, "fn({})", function.parameters.join(", ")),
+            Value::Native(ref native) => write!(f, "fn {}", native.name)
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, elements: &[Value]) -> fmt::Result {
+    write!(f, "[")?;
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write_element(f, element)?;
+    }
+    write!(f, "]")
+}
+
+fn write_map(f: &mut fmt::Formatter, map: &Map) -> fmt::Result {
+    write!(f, "{{")?;
+    for (i, (key, value)) in map.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}: ", quote_key(key))?;
+        write_element(f, value)?;
+    }
+    write!(f, "}}")
+}
+
+/// Keys are only quoted if they couldn't be written bare.
+fn quote_key(key: &str) -> String {
+    if key.starts_with(|c: char| c.is_alphabetic() || c == '_') && key.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        key.to_string()
+    }
+    else {
+        format!("{:?}", key)
+    }
+}
+
+/// Writes a value inside a list or map. Strings are quoted, so `["a, b"]` and
+/// `["a", "b"]` look different.
+fn write_element(f: &mut fmt::Formatter, value: &Value) -> fmt::Result {
+    match *value {
+        Value::String_(ref s) => write!(f, "{:?}", s),
+        _ => write!(f, "{}", value)
+    }
+}
+
+thread_local! {
+    /// The lists and maps being printed, by address.
+    static PRINTING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+    /// The pairs of lists or maps being compared, by address.
+    static COMPARING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+    Rc::as_ptr(rc) as *const () as usize
+}
+
+/// Runs `f` with `key` pushed on `stack`, or returns `None` if it is already
+/// there. Lists and maps can contain themselves, and this stops printing or
+/// comparing one from going round forever.
+fn guard<K: PartialEq, T, F: FnOnce() -> T>(stack: &'static LocalKey<RefCell<Vec<K>>>, key: K, f: F) -> Option<T> {
+    if stack.with(|stack| stack.borrow().contains(&key)) {
+        return None;
+    }
+    stack.with(|stack| stack.borrow_mut().push(key));
+    let result = f();
+    stack.with(|stack| stack.borrow_mut().pop());
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A list containing only itself.
+    fn cycle() -> Value {
+        let list = Value::list(Vec::new());
+        if let Value::List(ref elements) = list {
+            elements.borrow_mut().push(list.clone());
+        }
+        list
+    }
+
+    #[test]
+    fn lists_containing_themselves_can_be_printed() {
+        assert_eq!(cycle().to_string(), "[[...]]");
+        let repeated = Value::list(vec![Value::Integer(1)]);
+        assert_eq!(Value::list(vec![repeated.clone(), repeated]).to_string(), "[[1], [1]]");
+    }
+
+    #[test]
+    fn lists_containing_themselves_can_be_compared() {
+        assert_eq!(cycle(), cycle());
+        assert_ne!(cycle(), Value::list(vec![Value::list(Vec::new())]));
+    }
+
+    #[test]
+    fn summaries_cut_long_lists_and_maps_short() {
+        let numbers = Value::list((0..1000).map(Value::Integer).collect());
+        assert_eq!(numbers.summary(), "[0, 1, 2, 3, 4, ...]");
+        let mut map = Map::default();
+        map.insert("long".to_string(), Value::String_("x".repeat(100)));
+        map.insert("a key".to_string(), numbers);
+        assert_eq!(Value::map(map).summary(), format!("{{long: {:?}..., \"a key\": [0, 1, 2, 3, 4, ...]}}", "x".repeat(20)));
+        assert_eq!(cycle().summary(), "[[...]]");
+    }
+}
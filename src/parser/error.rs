@@ -0,0 +1,78 @@
+use std::fmt;
+
+use super::{ Span, TokenKind, Keyword };
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+    UnexpectedToken,
+    /// A closing bracket is missing, holds the span of the opening one.
+    Unclosed(Span),
+    /// Holds the quotes that would close the string.
+    UnterminatedString(String),
+    /// Holds the escape sequence as written, backslash included.
+    InvalidEscape(String),
+    UnterminatedComment,
+    /// Holds the literal as written.
+    InvalidNumber(String),
+    UnknownChar(char),
+    UnknownOperator(String),
+    /// `break` or `continue` outside of a loop, or `return` outside of a
+    /// function.
+    Misplaced(Keyword)
+}
+
+/// A problem found while lexing or parsing. `expected` describes what the
+/// parser was looking for and `found` is the token it got instead, or `None`
+/// at the end of the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub expected: Option<String>,
+    pub found: Option<TokenKind>,
+    pub span: Span
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError {
+            kind,
+            expected: None,
+            found: None,
+            span
+        }
+    }
+
+    pub fn unexpected(expected: &str, found: Option<TokenKind>, span: Span) -> ParseError {
+        ParseError {
+            kind: ParseErrorKind::UnexpectedToken,
+            expected: Some(expected.to_string()),
+            found,
+            span
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            ParseErrorKind::UnexpectedToken | ParseErrorKind::Unclosed(_) => {
+                let found = match self.found {
+                    Some(ref token) => format!("{}", token),
+                    None => "end of input".to_string()
+                };
+                match self.expected {
+                    Some(ref expected) => write!(f, "expected {}, found {}", expected, found),
+                    None => write!(f, "unexpected {}", found)
+                }
+            },
+            ParseErrorKind::UnterminatedString(_) => write!(f, "unterminated string"),
+            ParseErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            ParseErrorKind::InvalidEscape(ref escape) => write!(f, "invalid escape sequence `{}`", escape),
+            ParseErrorKind::InvalidNumber(ref number) => write!(f, "invalid number literal `{}`", number),
+            ParseErrorKind::UnknownChar(c) => write!(f, "unknown character '{}'", c.escape_debug()),
+            ParseErrorKind::UnknownOperator(ref op) => write!(f, "unknown operator `{}`", op),
+            ParseErrorKind::Misplaced(Keyword::Return) => write!(f, "`return` outside of a function"),
+            ParseErrorKind::Misplaced(ref keyword) => write!(f, "`{}` outside of a loop", keyword)
+        }
+    }
+}
@@ -1,55 +1,85 @@
-use std::str::Chars;
-use std::iter::Peekable;
+use super::Position;
 
 pub struct InputStream<'a> {
-    input: Peekable<Chars<'a>>,
-    line: i32,
-    column: i32
+    input: &'a str,
+    offset: usize,
+    line: u32,
+    column: u32
 }
 
 impl<'a> InputStream<'a> {
-    pub fn new(input: &'a String) -> InputStream<'a> {
+    pub fn new(input: &'a str) -> InputStream<'a> {
         InputStream {
-            input: input.chars().peekable(),
+            input,
+            offset: 0,
             line: 1,
-            column: 0
+            column: 1
+        }
+    }
+
+    /// A stream over `input` that starts reading at `position`, for parsing
+    /// a piece of a larger input.
+    pub fn starting_at(input: &'a str, position: Position) -> InputStream<'a> {
+        InputStream {
+            input,
+            offset: position.offset as usize,
+            line: position.line,
+            column: position.column
         }
     }
 
     pub fn next(&mut self) -> char {
-        match self.input.next() {
-            Some(c) if c == '\n' => {
-                self.line += 1;
-                self.column = 0;
-                c
-            },
+        match self.input[self.offset..].chars().next() {
             Some(c) => {
-                self.column += 1;
+                self.offset += c.len_utf8();
+                if c == '\n' {
+                    self.line += 1;
+                    self.column = 1;
+                }
+                else {
+                    self.column += 1;
+                }
                 c
             },
             None => '\0'
         }
     }
 
-    pub fn peek(&mut self) -> char {
-        if let Some(c) = self.input.peek() {
-            c.clone()
-        }
-        else {
-            '\0'
-        }
+    pub fn peek(&self) -> char {
+        self.input[self.offset..].chars().next().unwrap_or('\0')
     }
 
-    pub fn eof(&mut self) -> bool {
-        self.input.peek().is_none()
+    /// Everything that is left to read.
+    pub fn rest(&self) -> &'a str {
+        &self.input[self.offset..]
     }
 
-    pub fn panic(&self, message: &String) -> ! {
-        panic!(
-            "{message} ({line}:{column})",
-            message = message,
-            line = self.line,
-            column = self.column
-        );
+    /// The character after the next one, for the few places that need to
+    /// look two characters ahead.
+    pub fn peek_second(&self) -> char {
+        self.input[self.offset..].chars().nth(1).unwrap_or('\0')
+    }
+
+    pub fn eof(&self) -> bool {
+        self.offset >= self.input.len()
+    }
+
+    /// The whole input, including what has already been read.
+    pub fn source(&self) -> &'a str {
+        self.input
+    }
+
+    /// The input from `start` up to the next character to be read.
+    pub fn slice_from(&self, start: Position) -> &'a str {
+        &self.input[start.offset as usize..self.offset]
+    }
+
+    /// The position of the next character to be read.
+    pub fn position(&self) -> Position {
+        Position {
+            line: self.line,
+            column: self.column,
+            offset: self.offset as u32
+        }
     }
 }
@@ -1,4 +1,20 @@
-use super::InputStream;
+use std::fmt;
+use std::mem;
+
+use bigint::BigInt;
+
+use super::{ InputStream, Position, Span, ParseError, ParseErrorKind };
+
+/// Every operator the lexer knows. A run of operator characters is split
+/// into the longest operators from this list, so `x=-1` reads as `=` and `-`.
+pub const OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "//", "%", "**", "!",
+    "=", ":=",
+    "==", "!=", "<", ">", "<=", ">=",
+    "&&", "||",
+    "&", "|", "^", "<<", ">>",
+    ".."
+];
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Keyword {
@@ -7,59 +23,111 @@ pub enum Keyword {
     Else,
     Fn,
     True,
-    False
+    False,
+    Let,
+    In,
+    While,
+    For,
+    Break,
+    Continue,
+    Return
+}
+
+/// A piece of a string containing `${...}`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Literal(String),
+    /// The source of an embedded expression, between the braces.
+    Expression(Span)
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum Token { // TODO: add line/column to enums, pass up through parser for usable errors
+pub enum TokenKind {
     Punctuation(char),
-    Number(f64),
+    Integer(i64),
+    BigInteger(BigInt),
+    Float(f64),
     String_(String),
+    Interpolated(Vec<StringPart>),
     Identifier(String),
     Operator(String),
     Keyword(Keyword)
 }
 
+impl fmt::Display for Keyword {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let keyword = match *self {
+            Keyword::If => "if",
+            Keyword::Then => "then",
+            Keyword::Else => "else",
+            Keyword::Fn => "fn",
+            Keyword::True => "true",
+            Keyword::False => "false",
+            Keyword::Let => "let",
+            Keyword::In => "in",
+            Keyword::While => "while",
+            Keyword::For => "for",
+            Keyword::Break => "break",
+            Keyword::Continue => "continue",
+            Keyword::Return => "return"
+        };
+        write!(f, "{}", keyword)
+    }
+}
+
+impl fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TokenKind::Punctuation(c) => write!(f, "`{}`", c),
+            TokenKind::Integer(n) => write!(f, "integer `{}`", n),
+            TokenKind::BigInteger(ref n) => write!(f, "integer `{}`", n),
+            TokenKind::Float(n) => write!(f, "float `{:?}`", n),
+            TokenKind::String_(ref s) => write!(f, "string {:?}", s),
+            TokenKind::Interpolated(_) => write!(f, "interpolated string"),
+            TokenKind::Identifier(ref name) => write!(f, "identifier `{}`", name),
+            TokenKind::Operator(ref op) => write!(f, "operator `{}`", op),
+            TokenKind::Keyword(ref k) => write!(f, "keyword `{}`", k)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+    /// The text of the `##` comments just before the token, if any.
+    pub doc: Option<String>
+}
 
 pub struct TokenStream<'a> {
     input_stream: InputStream<'a>,
-    current: Option<Token>
+    current: Option<Token>,
+    errors: Vec<ParseError>,
+    /// Doc comments read since the last token.
+    doc: Option<String>
 }
 
 impl<'a> TokenStream<'a> {
     pub fn new(input_stream: InputStream) -> TokenStream {
         TokenStream {
             input_stream,
-            current: None
+            current: None,
+            errors: Vec::new(),
+            doc: None
         }
     }
 
     pub fn peek(&mut self) -> Option<Token> {
-        if let Some(_) = self.current {
-            self.current.clone()
-        }
-        else {
+        if self.current.is_none() {
             self.current = self.read_next();
-            if let Some(_) = self.current {
-                self.current.clone()
-            }
-            else {
-                None
-            }
         }
+        self.current.clone()
     }
 
     pub fn next(&mut self) -> Option<Token> {
-        let token = self.current.to_owned();
-        self.current = None;
-        if let tok @ Some(_) = token {
-            tok
-        }
-        else if let tok @ Some(_) = self.read_next() {
-            tok
-        }
-        else {
-            None
+        match self.current.take() {
+            tok @ Some(_) => tok,
+            None => self.read_next()
         }
     }
 
@@ -67,6 +135,17 @@ impl<'a> TokenStream<'a> {
         self.peek().is_none()
     }
 
+    /// The whole source being read.
+    pub fn source(&self) -> &'a str {
+        self.input_stream.source()
+    }
+
+    /// Takes the errors found while reading tokens so far. The lexer skips
+    /// over anything it cannot read, so these are not returned from `next`.
+    pub fn take_errors(&mut self) -> Vec<ParseError> {
+        mem::take(&mut self.errors)
+    }
+
     fn read_while<F>(&mut self, mut p: F) -> String where F: FnMut(&char) -> bool {
         let mut string = "".to_string();
         while !self.input_stream.eof() && p(&self.input_stream.peek()) {
@@ -75,130 +154,515 @@ impl<'a> TokenStream<'a> {
         string
     }
 
-    pub fn read_number(&mut self) -> Token {
-        let mut has_dot = false;
-        let number = self.read_while(|c|
-            if c.clone() == '.' {
-                if has_dot {
-                    false
-                }
-                else {
-                    has_dot = true;
-                    true
-                }
+    /// Reads a number literal. Anything that runs on from a number, like the
+    /// `z` in `12z`, is read as part of it so the whole literal is reported
+    /// if it is malformed.
+    pub fn read_number(&mut self) -> TokenKind {
+        let start = self.input_stream.position();
+        let mut literal = String::new();
+        let prefixed = self.input_stream.peek() == '0' && matches!(self.input_stream.peek_second(), 'x' | 'b' | 'o');
+        loop {
+            let c = self.input_stream.peek();
+            let next = self.input_stream.peek_second();
+            let continues = if prefixed {
+                is_identifier(&c)
+            }
+            else if c == '.' {
+                // `1.x` and `1..` are left for whatever follows the number
+                !literal.contains('.') && !literal.contains(['e', 'E']) && !is_identifier_start(&next) && next != '.'
+            }
+            else if c == '+' || c == '-' {
+                literal.ends_with(['e', 'E']) && next.is_ascii_digit()
             }
             else {
-                c.is_digit(10)
+                is_identifier(&c)
+            };
+            if !continues {
+                break;
             }
-        );
-        Token::Number(number.parse::<f64>().unwrap())
+            literal.push(self.input_stream.next());
+        }
+
+        match parse_number(&literal) {
+            Some(number) => number,
+            None => {
+                let span = Span::new(start, self.input_stream.position());
+                self.errors.push(ParseError::new(ParseErrorKind::InvalidNumber(literal), span));
+                TokenKind::Integer(0)
+            }
+        }
     }
 
-    pub fn read_identifier(&mut self) -> Token {
+    pub fn read_identifier(&mut self) -> TokenKind {
         let identifier = self.read_while(is_identifier);
         if let Some(keyword) = get_keyword(&identifier) {
-            Token::Keyword(keyword)
+            TokenKind::Keyword(keyword)
         }
         else {
-            Token::Identifier(identifier)
+            TokenKind::Identifier(identifier)
         }
     }
 
-    pub fn read_escaped(&mut self, end: char) -> String {
-        let mut escaped = false;
+    /// Reads a string that opens and closes with `quotes`, handling escapes
+    /// and `${...}`.
+    pub fn read_escaped(&mut self, quotes: &str) -> Vec<StringPart> {
+        let start = self.input_stream.position();
+        let mut parts: Vec<StringPart> = Vec::new();
         let mut string = "".to_string();
-        self.input_stream.next();
-        while !self.input_stream.eof() {
+        self.skip(quotes.len());
+        loop {
+            if self.input_stream.eof() {
+                let span = Span::new(start, self.input_stream.position());
+                self.errors.push(ParseError::new(ParseErrorKind::UnterminatedString(quotes.to_string()), span));
+                break;
+            }
+            if self.input_stream.rest().starts_with(quotes) {
+                self.skip(quotes.len());
+                break;
+            }
+            let escape_start = self.input_stream.position();
             let c = self.input_stream.next();
-            if escaped {
+            if c == '\\' {
+                if let Some(escaped) = self.read_escape(escape_start) {
+                    string.push(escaped);
+                }
+            }
+            else if c == '$' && self.input_stream.peek() == '{' {
+                self.input_stream.next();
+                parts.push(StringPart::Literal(mem::take(&mut string)));
+                parts.push(StringPart::Expression(self.skip_embedded()));
+            }
+            // a line break in a file with CRLF line endings is still just
+            // `\n` in the string, so `"""` strings strip it the same way
+            else if c == '\r' && self.input_stream.peek() == '\n' {
+                continue;
+            }
+            else {
                 string.push(c);
-                escaped = false;
             }
-            else if c == '\\' {
-                escaped = true;
+        }
+        if !string.is_empty() || parts.is_empty() {
+            parts.push(StringPart::Literal(string));
+        }
+        parts
+    }
+
+    /// Skips the tokens of an expression embedded in a string, up to the `}`
+    /// closing it, and returns their span. The parser reads them later.
+    fn skip_embedded(&mut self) -> Span {
+        let start = self.input_stream.position();
+        let mut depth = 0;
+        loop {
+            match self.read_next() {
+                Some(Token { kind: TokenKind::Punctuation('}'), span, .. }) if depth == 0 =>
+                    return Span::new(start, span.start),
+                Some(Token { kind: TokenKind::Punctuation('}'), .. }) => depth -= 1,
+                Some(Token { kind: TokenKind::Punctuation('{'), .. }) => depth += 1,
+                Some(_) => {},
+                None => return Span::new(start, self.input_stream.position())
             }
-            else if c == end {
+        }
+    }
+
+    /// Reads the rest of an escape sequence whose `\` is at `start`. Returns
+    /// `None`, having recorded an error, if the sequence isn't valid.
+    fn read_escape(&mut self, start: Position) -> Option<char> {
+        let escaped = match self.input_stream.next() {
+            'n' => Some('\n'),
+            't' => Some('\t'),
+            'r' => Some('\r'),
+            '0' => Some('\0'),
+            '\\' => Some('\\'),
+            '"' => Some('"'),
+            '$' => Some('$'),
+            // `\xNN` is limited to ASCII, anything above needs `\u{...}`
+            'x' => {
+                let mut count = 0;
+                let digits = self.read_while(|c| {
+                    count += 1;
+                    count <= 2 && c.is_ascii_hexdigit()
+                });
+                u8::from_str_radix(&digits, 16).ok()
+                    .filter(|&byte| digits.len() == 2 && byte.is_ascii())
+                    .map(char::from)
+            },
+            'u' if self.input_stream.peek() == '{' => {
+                self.input_stream.next();
+                let digits = self.read_while(char::is_ascii_hexdigit);
+                if self.input_stream.peek() == '}' {
+                    self.input_stream.next();
+                    u32::from_str_radix(&digits, 16).ok()
+                        .filter(|_| digits.len() <= 6)
+                        .and_then(char::from_u32)
+                }
+                else {
+                    None
+                }
+            },
+            // the string is unterminated, which is reported already
+            _ if self.input_stream.eof() => return None,
+            _ => None
+        };
+        if escaped.is_none() {
+            let span = Span::new(start, self.input_stream.position());
+            let escape = self.input_stream.slice_from(start).to_string();
+            self.errors.push(ParseError::new(ParseErrorKind::InvalidEscape(escape), span));
+        }
+        escaped
+    }
+
+    pub fn read_string(&mut self) -> TokenKind {
+        string_token(self.read_escaped("\""))
+    }
+
+    /// Reads a `"""` string, which can span several lines. When the opening
+    /// quotes end their line, that line break is dropped. When the closing
+    /// quotes are on a line of their own, that line is dropped and its
+    /// indentation is removed from every line of the string.
+    pub fn read_multiline_string(&mut self) -> TokenKind {
+        let mut parts = self.read_escaped("\"\"\"");
+        strip_indentation(&mut parts);
+        string_token(parts)
+    }
+
+    /// Reads `r"..."` or `r#"..."#`, which ends at a `"` followed by as many
+    /// `#` as it started with. Nothing inside is escaped or interpolated.
+    pub fn read_raw_string(&mut self) -> TokenKind {
+        let start = self.input_stream.position();
+        self.input_stream.next();
+        let hashes = self.read_while(|c| *c == '#');
+        self.input_stream.next();
+        let quotes = format!("\"{}", hashes);
+        let mut string = String::new();
+        loop {
+            if self.input_stream.eof() {
+                let span = Span::new(start, self.input_stream.position());
+                self.errors.push(ParseError::new(ParseErrorKind::UnterminatedString(quotes), span));
+                break;
+            }
+            if self.input_stream.rest().starts_with(&quotes) {
+                self.skip(quotes.len());
                 break;
             }
+            string.push(self.input_stream.next());
+        }
+        TokenKind::String_(string)
+    }
+
+    /// Skips `count` characters.
+    fn skip(&mut self, count: usize) {
+        for _ in 0..count {
+            self.input_stream.next();
+        }
+    }
+
+    /// Reads the longest known operator starting at the current character.
+    /// Returns `None`, having recorded an error, if there isn't one.
+    pub fn read_operator(&mut self) -> Option<TokenKind> {
+        let start = self.input_stream.position();
+        let mut operator = String::new();
+        self.read_while(|c| {
+            operator.push(*c);
+            if OPERATORS.iter().any(|op| op.starts_with(operator.as_str())) {
+                true
+            }
             else {
-                string.push(c);
+                operator.pop();
+                false
             }
+        });
+        if OPERATORS.contains(&operator.as_str()) {
+            Some(TokenKind::Operator(operator))
+        }
+        else {
+            if operator.is_empty() {
+                operator.push(self.input_stream.next());
+            }
+            let span = Span::new(start, self.input_stream.position());
+            self.errors.push(ParseError::new(ParseErrorKind::UnknownOperator(operator), span));
+            None
         }
-        string
     }
 
-    pub fn read_string(&mut self) -> Token {
-        let string = self.read_escaped('"');
-        Token::String_(string)
+    /// Skips whitespace and comments, keeping the text of doc comments for
+    /// the next token.
+    fn skip_whitespace(&mut self) {
+        loop {
+            self.read_while(|c| c.is_whitespace());
+            let rest = self.input_stream.rest();
+            if rest.starts_with("#[") {
+                self.skip_block_comment();
+            }
+            else if rest.starts_with("##") {
+                self.read_doc_comment();
+            }
+            else if rest.starts_with('#') {
+                self.skip_comment();
+            }
+            else {
+                break;
+            }
+        }
     }
 
     pub fn skip_comment(&mut self) {
-        self.read_while(|c|
-            c.clone() != '\n'
-        );
+        self.read_while(|c| *c != '\n');
     }
 
-    pub fn read_next(&mut self) -> Option<Token> {
-        self.read_while(|c| c.is_whitespace());
-        if self.input_stream.eof() {
-            return None;
+    /// Skips a `#[ ... ]#` comment, which can contain other block comments.
+    pub fn skip_block_comment(&mut self) {
+        let start = self.input_stream.position();
+        let mut depth = 0;
+        loop {
+            let rest = self.input_stream.rest();
+            if rest.starts_with("#[") {
+                self.skip(2);
+                depth += 1;
+            }
+            else if rest.starts_with("]#") {
+                self.skip(2);
+                depth -= 1;
+                if depth == 0 {
+                    return;
+                }
+            }
+            else if self.input_stream.eof() {
+                let span = Span::new(start, self.input_stream.position());
+                self.errors.push(ParseError::new(ParseErrorKind::UnterminatedComment, span));
+                return;
+            }
+            else {
+                self.input_stream.next();
+            }
         }
-        match self.input_stream.peek() {
-            c if c == '#' => {
-                self.skip_comment();
-                self.read_next()
+    }
+
+    /// Reads a `##` comment. Consecutive lines are joined into one doc.
+    pub fn read_doc_comment(&mut self) {
+        self.skip(2);
+        let line = self.read_while(|c| *c != '\n');
+        let line = line.strip_prefix(' ').unwrap_or(&line);
+        match self.doc {
+            Some(ref mut doc) => {
+                doc.push('\n');
+                doc.push_str(line);
             },
-            c if c == '"' =>
-                Some(self.read_string()),
-            c if c.is_digit(10) =>
-                Some(self.read_number()),
-            c if is_identifier_start(&c) =>
-                Some(self.read_identifier()),
-            c if is_punctuation(&c) =>
-                Some(Token::Punctuation(self.input_stream.next())),
-            c if is_operator(&c) =>
-                Some(Token::Operator(self.read_while(is_operator))),
-            c => self.input_stream.panic(&format!("Cannot handle char: '{}'", c.escape_debug()))
+            None => self.doc = Some(line.to_string())
         }
     }
 
-    pub fn panic(&self, message: String) -> ! {
-        self.input_stream.panic(&message)
+    pub fn read_next(&mut self) -> Option<Token> {
+        // unknown characters and operators are skipped, having recorded an
+        // error, and lexing carries on with whatever follows them
+        loop {
+            self.skip_whitespace();
+            if self.input_stream.eof() {
+                return None;
+            }
+            let start = self.input_stream.position();
+            // taken before reading the token, because reading a string can
+            // read the tokens inside `${...}` too
+            let doc = self.doc.take();
+            let kind = match self.input_stream.peek() {
+                '"' if self.input_stream.rest().starts_with("\"\"\"") =>
+                    self.read_multiline_string(),
+                '"' =>
+                    self.read_string(),
+                'r' if is_raw_string_start(self.input_stream.rest()) =>
+                    self.read_raw_string(),
+                c if c.is_ascii_digit() || (c == '.' && self.input_stream.peek_second().is_ascii_digit()) =>
+                    self.read_number(),
+                c if is_identifier_start(&c) =>
+                    self.read_identifier(),
+                '.' if self.input_stream.peek_second() == '.' =>
+                    self.read_operator().expect("`..` is an operator"),
+                c if is_punctuation(&c) =>
+                    TokenKind::Punctuation(self.input_stream.next()),
+                // `:` is punctuation unless it starts `:=`
+                ':' if self.input_stream.peek_second() != '=' =>
+                    TokenKind::Punctuation(self.input_stream.next()),
+                c if is_operator(&c) => match self.read_operator() {
+                    Some(operator) => operator,
+                    None => {
+                        self.doc = doc;
+                        continue;
+                    }
+                },
+                c => {
+                    self.input_stream.next();
+                    let span = Span::new(start, self.input_stream.position());
+                    self.errors.push(ParseError::new(ParseErrorKind::UnknownChar(c), span));
+                    self.doc = doc;
+                    continue;
+                }
+            };
+            return Some(Token {
+                kind,
+                span: Span::new(start, self.input_stream.position()),
+                doc
+            });
+        }
     }
 }
 
-fn is_operator(c: &char) -> bool {
-    match c.clone() {
-        '+' | '-' | '*' | '/' | '%' | '=' | '|' | '&' | '<' | '>' | '!' => true,
-        _ => false
+/// The value of a number literal, or `None` if it is malformed. Digits can
+/// be separated with `_`, but a decimal number or any part of it can't start
+/// or end with one. Literals without a `.` or an exponent are integers.
+fn parse_number(literal: &str) -> Option<TokenKind> {
+    let radix = match literal.get(..2) {
+        Some("0x") => 16,
+        Some("0b") => 2,
+        Some("0o") => 8,
+        _ => 10
+    };
+    if radix != 10 {
+        return parse_integer(&literal[2..].replace('_', ""), radix);
+    }
+
+    let parts_valid = literal
+        .split(['.', 'e', 'E'])
+        .map(|part| part.trim_start_matches(['+', '-']))
+        .all(|part| part.starts_with(|c: char| c.is_ascii_digit()) && !part.ends_with('_'));
+    if !parts_valid {
+        return None;
+    }
+    let digits = literal.replace('_', "");
+    if literal.contains(['.', 'e', 'E']) {
+        digits.parse::<f64>().ok().map(TokenKind::Float)
+    }
+    else {
+        parse_integer(&digits, 10)
     }
 }
 
-fn is_punctuation(c: &char) -> bool {
-    match c.clone() {
-        ',' | ';' | '(' | ')' | '{' | '}' | '[' | ']' => true,
-        _ => false
+fn parse_integer(digits: &str, radix: u32) -> Option<TokenKind> {
+    let n = BigInt::from_str_radix(digits, radix)?;
+    Some(match n.to_i64() {
+        Some(n) => TokenKind::Integer(n),
+        None => TokenKind::BigInteger(n)
+    })
+}
+
+fn string_token(mut parts: Vec<StringPart>) -> TokenKind {
+    match parts.as_mut_slice() {
+        [StringPart::Literal(string)] => TokenKind::String_(mem::take(string)),
+        _ => TokenKind::Interpolated(parts)
     }
 }
 
-fn get_keyword(identifier: &String) -> Option<Keyword> {
-    match identifier.clone().as_ref() {
+/// Removes the line breaks and indentation around the text of a `"""`
+/// string, as described on `read_multiline_string`.
+fn strip_indentation(parts: &mut [StringPart]) {
+    let mut indentation = 0;
+    if let Some(StringPart::Literal(last)) = parts.last_mut() {
+        if let Some(line_start) = last.rfind('\n') {
+            let closing_line = &last[line_start + 1..];
+            if closing_line.chars().all(|c| c == ' ' || c == '\t') {
+                indentation = closing_line.len();
+                last.truncate(line_start);
+            }
+        }
+    }
+    let mut opening_line_dropped = false;
+    if let Some(StringPart::Literal(first)) = parts.first_mut() {
+        if let Some(rest) = first.trim_start_matches([' ', '\t']).strip_prefix('\n') {
+            *first = rest.to_string();
+            opening_line_dropped = true;
+        }
+    }
+
+    for (i, part) in parts.iter_mut().enumerate() {
+        if let StringPart::Literal(string) = part {
+            let lines: Vec<&str> = string.split('\n').enumerate().map(|(j, line)| {
+                if j > 0 || (i == 0 && opening_line_dropped) {
+                    let width = line.len() - line.trim_start_matches([' ', '\t']).len();
+                    &line[width.min(indentation)..]
+                }
+                else {
+                    line
+                }
+            }).collect();
+            *string = lines.join("\n");
+        }
+    }
+}
+
+fn is_raw_string_start(rest: &str) -> bool {
+    rest.strip_prefix('r').is_some_and(|rest| rest.trim_start_matches('#').starts_with('"'))
+}
+
+fn is_operator(c: &char) -> bool {
+    matches!(c, '+' | '-' | '*' | '/' | '%' | '=' | '|' | '&' | '<' | '>' | '!' | ':' | '^')
+}
+
+fn is_punctuation(c: &char) -> bool {
+    matches!(c, ',' | ';' | '(' | ')' | '{' | '}' | '[' | ']' | '.')
+}
+
+fn get_keyword(identifier: &str) -> Option<Keyword> {
+    match identifier {
         "if" => Some(Keyword::If),
         "else" => Some(Keyword::Else),
         "then" => Some(Keyword::Then),
         "fn" => Some(Keyword::Fn),
         "true" => Some(Keyword::True),
         "false" => Some(Keyword::False),
+        "let" => Some(Keyword::Let),
+        "in" => Some(Keyword::In),
+        "while" => Some(Keyword::While),
+        "for" => Some(Keyword::For),
+        "break" => Some(Keyword::Break),
+        "continue" => Some(Keyword::Continue),
+        "return" => Some(Keyword::Return),
         _ => None
     }
 }
 
 fn is_identifier_start(c: &char) -> bool {
-    let c = c.clone();
-    c.is_alphabetic() || c == '_'
+    c.is_alphabetic() || *c == '_'
 }
 
 fn is_identifier(c: &char) -> bool {
-    let c = c.clone();
-    c.is_alphanumeric() || c == '_'
+    c.is_alphanumeric() || *c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(code: &str) -> Vec<TokenKind> {
+        let mut token_stream = TokenStream::new(InputStream::new(code));
+        let mut tokens = Vec::new();
+        while let Some(token) = token_stream.next() {
+            tokens.push(token.kind);
+        }
+        tokens
+    }
+
+    #[test]
+    fn multiline_strings_strip_crlf_line_breaks() {
+        let lf = tokens("\"\"\"\n    a\n      b\n    \"\"\"");
+        let crlf = tokens("\"\"\"\r\n    a\r\n      b\r\n    \"\"\"");
+        assert_eq!(lf, vec![TokenKind::String_("a\n  b".to_string())]);
+        assert_eq!(crlf, lf);
+    }
+
+    #[test]
+    fn escaped_carriage_returns_are_kept() {
+        assert_eq!(tokens("\"a\\r\nb\""), vec![TokenKind::String_("a\r\nb".to_string())]);
+    }
+
+    #[test]
+    fn long_runs_of_unknown_characters_are_skipped() {
+        for bad in ["@", "\\"] {
+            let code = format!("1{}2", bad.repeat(200_000));
+            let mut token_stream = TokenStream::new(InputStream::new(&code));
+            let mut tokens = Vec::new();
+            while let Some(token) = token_stream.next() {
+                tokens.push(token.kind);
+            }
+            assert_eq!(tokens, vec![TokenKind::Integer(1), TokenKind::Integer(2)]);
+            assert_eq!(token_stream.take_errors().len(), 200_000);
+        }
+    }
 }
@@ -1,7 +1,12 @@
+mod span;
+mod error;
 mod input_stream;
 mod lexer;
+#[allow(clippy::module_inception)]
 mod parser;
 
+pub use self::span::*;
+pub use self::error::*;
 pub use self::input_stream::*;
 pub use self::lexer::*;
 pub use self::parser::*;
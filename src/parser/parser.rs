@@ -1,47 +1,38 @@
-use super::{ TokenStream, Token, Keyword };
-use std::fmt;
+use std::mem;
 
-impl fmt::Debug for Box<Fn(Vec<AST>) -> AST> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "[native code]")
-    }
-}
+use bigint::BigInt;
 
-impl PartialEq for Box<Fn(Vec<AST>) -> AST> {
-    fn eq(&self, _: &Box<Fn(Vec<AST>) -> AST>) -> bool {
-        false
-    }
-}
-
-impl Clone for Box<Fn(Vec<AST>) -> AST> {
-    fn clone(&self) -> Box<Fn(Vec<AST>) -> AST> {
-        Box::new(|args: Vec<AST>| {
-            let mut string = "".to_string();
-            for i in args.iter() {
-                let s = match i {
-                    AST::String_(lit) => lit.clone(),
-                    AST::Number(num) => format!("{}", num),
-                    AST::Boolean(b) => if *b { "true" } else { "false" }.to_string(),
-                    x => format!("{:?}", x)
-                };
-                string.push_str(&s);
-            }
-            print!("{}", string);
-            AST::Boolean(true)
-        })
-    }
-}
+use super::{ InputStream, TokenStream, Token, TokenKind, StringPart, Keyword, Position, Span, ParseError, ParseErrorKind };
 
 #[derive(Debug, Clone, PartialEq)]
-pub enum AST {
-    Number(f64),
+pub enum ASTKind {
+    Integer(i64),
+    BigInteger(BigInt),
+    Float(f64),
     String_(String),
+    /// A string with embedded expressions, the parts are joined together
+    /// after converting them to strings.
+    Interpolation(Vec<AST>),
     Boolean(bool),
     Variable(String),
+    List(Vec<AST>),
+    /// A map literal, with its entries in the order they were written.
+    Map(Vec<(String, AST)>),
+    /// `object[index]`.
+    Index {
+        object: Box<AST>,
+        index: Box<AST>
+    },
+    /// `object.name`.
+    Field {
+        object: Box<AST>,
+        name: String
+    },
     Function {
         parameters: Vec<String>,
         body: Box<AST>,
-        native: Option<Box<Fn(Vec<AST>) -> AST>>
+        /// From the `##` comments before the function.
+        doc: Option<String>
     },
     Call {
         function: Box<AST>,
@@ -55,191 +46,544 @@ pub enum AST {
     Assign {
         operator: String,
         left: Box<AST>,
-        right: Box<AST>
+        right: Box<AST>,
+        /// From the `##` comments before the assignment.
+        doc: Option<String>
     },
     Binary {
         operator: String,
         left: Box<AST>,
         right: Box<AST>
     },
-    Program(Vec<AST>)
-    /*Let {
+    Unary {
+        operator: String,
+        operand: Box<AST>
+    },
+    Let {
         variables: Vec<(String, AST)>,
         body: Box<AST>
-    }*/
+    },
+    While {
+        condition: Box<AST>,
+        body: Box<AST>
+    },
+    /// `for variable in iterable body`.
+    For {
+        variable: String,
+        iterable: Box<AST>,
+        body: Box<AST>
+    },
+    Break,
+    Continue,
+    /// `return` without a value returns `nil`.
+    Return(Option<Box<AST>>),
+    Program(Vec<AST>),
+    /// Stands in for source that could not be parsed.
+    Error
+}
+
+/// A node in the syntax tree, along with the span of source it was parsed
+/// from.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AST {
+    pub kind: ASTKind,
+    pub span: Span
+}
+
+impl AST {
+    pub fn new(kind: ASTKind, span: Span) -> AST {
+        AST { kind, span }
+    }
+}
+
+/// An entry in a block. A `let` without `in` is kept apart until the whole
+/// block has been read, because its body is the rest of the block.
+enum Statement {
+    Expression(AST),
+    Let(Vec<(String, AST)>, Span)
 }
 
 pub struct Parser<'a> {
-    token_stream: TokenStream<'a>
+    token_stream: TokenStream<'a>,
+    previous: Span,
+    errors: Vec<ParseError>,
+    /// Whether `break` and `continue` are allowed here.
+    in_loop: bool,
+    /// Whether `return` is allowed here.
+    in_function: bool
 }
 
 impl<'a> Parser<'a> {
     pub fn new(token_stream: TokenStream<'a>) -> Parser<'a> {
         Parser {
-            token_stream
+            token_stream,
+            previous: Span::default(),
+            errors: Vec::new(),
+            in_loop: false,
+            in_function: false
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<AST, Vec<ParseError>> {
+        let (program, errors) = self.parse_partial();
+        if errors.is_empty() {
+            Ok(program)
+        }
+        else {
+            Err(errors)
         }
     }
 
-    pub fn parse(&mut self) -> AST {
-        let mut program: Vec<AST> = Vec::new();
+    /// Parses the whole input, carrying on past syntax errors. Anything that
+    /// could not be parsed is replaced with an `ASTKind::Error` node, and the
+    /// errors are returned in the order they appear in the source.
+    pub fn parse_partial(&mut self) -> (AST, Vec<ParseError>) {
+        let start = self.start();
+        let mut program: Vec<Statement> = Vec::new();
 
         while !self.token_stream.eof() {
-            program.push(self.parse_expression());
-            if !self.token_stream.eof() {
-                self.skip_punctuation(';');
+            let expression_start = self.start();
+            match self.parse_statement() {
+                Ok(statement) => {
+                    program.push(statement);
+                    if self.token_stream.eof() || self.is_punctuation(';') {
+                        self.next_token();
+                        continue;
+                    }
+                    let error = self.unexpected("`;`");
+                    self.recover(error);
+                },
+                Err(error) => {
+                    self.recover(error);
+                    program.push(Statement::Expression(AST::new(ASTKind::Error, self.finish(expression_start))));
+                }
+            }
+            // closing brackets have nothing to close at the top level, so
+            // skip over them until the end of the statement
+            while !self.token_stream.eof() && !self.is_punctuation(';') {
+                self.next_token();
+                self.synchronize();
             }
+            self.next_token();
         }
 
-        AST::Program(program)
+        let mut errors = self.token_stream.take_errors();
+        errors.append(&mut self.errors);
+        errors.sort_by_key(|error| error.span.start.offset);
+
+        (AST::new(ASTKind::Program(scope_statements(program)), self.finish(start)), errors)
     }
 
-    fn parse_expression(&mut self) -> AST {
-        let atom = self.parse_atom();
-        let result = self.maybe_binary(atom, 0);
-        if self.is_punctuation('(') {
-            self.parse_call(result)
+    /// Parses an entry of a block, which may be a `let` declaring variables
+    /// for the rest of the block.
+    fn parse_statement(&mut self) -> Result<Statement, ParseError> {
+        if !self.is_keyword(Keyword::Let) {
+            return Ok(Statement::Expression(self.parse_expression()?));
+        }
+        let start = self.start();
+        let doc = self.doc();
+        let mut variables = self.parse_let_variables()?;
+        if let Some(&mut (_, ref mut value)) = variables.first_mut() {
+            attach_doc(value, doc);
+        }
+        if self.is_keyword(Keyword::In) {
+            self.next_token();
+            let body = Box::new(self.parse_expression()?);
+            Ok(Statement::Expression(AST::new(ASTKind::Let { variables, body }, self.finish(start))))
         }
         else {
-            result
+            Ok(Statement::Let(variables, self.finish(start)))
         }
     }
 
-    fn parse_atom(&mut self) -> AST {
+    fn parse_expression(&mut self) -> Result<AST, ParseError> {
+        let doc = self.doc();
+        let atom = self.parse_atom()?;
+        let mut result = self.maybe_binary(atom, 0)?;
+        attach_doc(&mut result, doc);
+        Ok(result)
+    }
+
+    fn parse_atom(&mut self) -> Result<AST, ParseError> {
+        let start = self.start();
         let result = {
             if self.is_punctuation('(') {
-                self.token_stream.next();
-                let result = self.parse_expression();
-                self.skip_punctuation(')');
+                self.next_token();
+                let opening = self.previous;
+                let mut result = self.parse_expression()?;
+                self.skip_closing(')', opening)?;
+                result.span = self.finish(start);
                 result
             }
+            else if self.is_map() {
+                let entries = self.delimited('{', '}', ',', Parser::parse_entry, |span| {
+                    (String::new(), AST::new(ASTKind::Error, span))
+                })?;
+                AST::new(ASTKind::Map(entries), self.finish(start))
+            }
             else if self.is_punctuation('{') {
-                self.parse_program()
+                self.parse_program()?
+            }
+            else if self.is_punctuation('[') {
+                let items = self.delimited('[', ']', ',', Parser::parse_expression, |span| AST::new(ASTKind::Error, span))?;
+                AST::new(ASTKind::List(items), self.finish(start))
             }
             else if self.is_keyword(Keyword::If) {
-                self.parse_if()
+                self.parse_if()?
             }
             else if self.is_keyword(Keyword::True) || self.is_keyword(Keyword::False) {
-                self.parse_boolean()
+                self.parse_boolean()?
             }
             else if self.is_keyword(Keyword::Fn) {
-                self.token_stream.next();
-                self.parse_function()
+                self.next_token();
+                self.parse_function(start)?
+            }
+            else if self.is_keyword(Keyword::Let) {
+                self.parse_let()?
+            }
+            else if self.is_keyword(Keyword::While) {
+                self.parse_while()?
+            }
+            else if self.is_keyword(Keyword::For) {
+                self.parse_for()?
+            }
+            else if self.is_keyword(Keyword::Break) || self.is_keyword(Keyword::Continue) || self.is_keyword(Keyword::Return) {
+                self.parse_jump()?
+            }
+            else if let Some(operator) = self.is_operator().filter(|op| is_prefix(op)) {
+                self.next_token();
+                let atom = self.parse_atom()?;
+                let operand = Box::new(self.maybe_binary(atom, PREFIX_PRECEDENCE)?);
+                AST::new(ASTKind::Unary { operator, operand }, self.finish(start))
             }
             else {
-                let token = self.token_stream.next();
-                match token {
-                    Some(Token::Identifier(name)) => AST::Variable(name),
-                    Some(Token::Number(number)) => AST::Number(number),
-                    Some(Token::String_(string)) => AST::String_(string),
-                    token => self.token_stream.panic(format!("Unexpected token: {:?}", token))
-                }
+                let kind = match self.token_stream.peek().map(|t| t.kind) {
+                    Some(TokenKind::Identifier(name)) => ASTKind::Variable(name),
+                    Some(TokenKind::Integer(number)) => ASTKind::Integer(number),
+                    Some(TokenKind::BigInteger(number)) => ASTKind::BigInteger(number),
+                    Some(TokenKind::Float(number)) => ASTKind::Float(number),
+                    Some(TokenKind::String_(string)) => ASTKind::String_(string),
+                    Some(TokenKind::Interpolated(parts)) => {
+                        self.next_token();
+                        let interpolation = self.parse_interpolation(parts);
+                        return self.parse_postfix(interpolation);
+                    },
+                    _ => return Err(self.unexpected("expression"))
+                };
+                self.next_token();
+                AST::new(kind, self.previous)
             }
         };
-        if self.is_punctuation('(') {
-            self.parse_call(result)
-        }
-        else {
-            result
+        self.parse_postfix(result)
+    }
+
+    /// Parses any calls, indexing and field access following `ast`, as in
+    /// `f(x)[0].name`.
+    fn parse_postfix(&mut self, mut ast: AST) -> Result<AST, ParseError> {
+        loop {
+            if self.is_punctuation('(') {
+                ast = self.parse_call(ast)?;
+            }
+            else if self.is_punctuation('[') {
+                ast = self.parse_index(ast)?;
+            }
+            else if self.is_punctuation('.') {
+                self.next_token();
+                let name = self.parse_field_name()?;
+                let span = ast.span.to(self.previous);
+                ast = AST::new(ASTKind::Field { object: Box::new(ast), name }, span);
+            }
+            else {
+                return Ok(ast);
+            }
         }
     }
 
-    fn parse_program(&mut self) -> AST {
-        let mut program = self.delimited_expressions('{', '}', ';');
-        match program.len() {
-            0 => AST::Boolean(false),
+    fn parse_program(&mut self) -> Result<AST, ParseError> {
+        let start = self.start();
+        let statements = self.delimited('{', '}', ';', Parser::parse_statement, |span| {
+            Statement::Expression(AST::new(ASTKind::Error, span))
+        })?;
+        let mut program = scope_statements(statements);
+        let span = self.finish(start);
+        Ok(match program.len() {
+            0 => AST::new(ASTKind::Boolean(false), span),
             1 => program.remove(0),
-            _ => AST::Program(program)
-        }
+            _ => AST::new(ASTKind::Program(program), span)
+        })
     }
 
-    fn parse_call(&mut self, ast: AST) -> AST {
-        AST::Call {
+    fn parse_interpolation(&mut self, parts: Vec<StringPart>) -> AST {
+        let span = self.previous;
+        let parts = parts.into_iter().map(|part| match part {
+            StringPart::Literal(string) => AST::new(ASTKind::String_(string), span),
+            StringPart::Expression(code) => self.parse_embedded(code)
+        }).collect();
+        AST::new(ASTKind::Interpolation(parts), span)
+    }
+
+    /// Parses the expression inside a `${...}` with a parser of its own. The
+    /// lexer has already read this code once, so only errors from parsing
+    /// it are kept.
+    fn parse_embedded(&mut self, code: Span) -> AST {
+        let input_stream = InputStream::starting_at(self.token_stream.source(), code.start);
+        let mut parser = Parser::new(TokenStream::new(input_stream));
+        // errors at the end of the input are placed after the last token,
+        // which needs to be inside the string even if there are none
+        parser.previous = Span::new(code.start, code.start);
+        parser.in_loop = self.in_loop;
+        parser.in_function = self.in_function;
+        let result = parser.parse_expression().and_then(|expression| {
+            if parser.start().offset < code.end.offset {
+                Err(parser.unexpected("`}`"))
+            }
+            else {
+                Ok(expression)
+            }
+        });
+        self.errors.append(&mut parser.errors);
+        result.unwrap_or_else(|error| {
+            self.errors.push(error);
+            AST::new(ASTKind::Error, code)
+        })
+    }
+
+    fn parse_call(&mut self, ast: AST) -> Result<AST, ParseError> {
+        let arguments = self.delimited('(', ')', ',', Parser::parse_expression, |span| AST::new(ASTKind::Error, span))?;
+        let span = ast.span.to(self.previous);
+        Ok(AST::new(ASTKind::Call {
             function: Box::new(ast),
-            arguments: self.delimited_expressions('(', ')', ',')
-        }
+            arguments
+        }, span))
+    }
+
+    fn parse_index(&mut self, ast: AST) -> Result<AST, ParseError> {
+        self.skip_punctuation('[')?;
+        let opening = self.previous;
+        let index = self.parse_expression()?;
+        self.skip_closing(']', opening)?;
+        let span = ast.span.to(self.previous);
+        Ok(AST::new(ASTKind::Index {
+            object: Box::new(ast),
+            index: Box::new(index)
+        }, span))
     }
 
-    fn parse_boolean(&mut self) -> AST {
-        match self.token_stream.next() {
-            Some(Token::Keyword(Keyword::True)) => AST::Boolean(true),
-            Some(Token::Keyword(Keyword::False))=> AST::Boolean(false),
-            otherwise => self.token_stream.panic(format!("Unkown bool {:?}", otherwise))
+    /// Parses a `key: value` entry of a map literal. Keys are identifiers or
+    /// strings.
+    fn parse_entry(&mut self) -> Result<(String, AST), ParseError> {
+        let key = match self.token_stream.peek().map(|t| t.kind) {
+            Some(TokenKind::String_(key)) => {
+                self.next_token();
+                key
+            },
+            _ => self.parse_field_name()?
+        };
+        self.skip_punctuation(':')?;
+        Ok((key, self.parse_expression()?))
+    }
+
+    fn parse_field_name(&mut self) -> Result<String, ParseError> {
+        if let Some(TokenKind::Identifier(name)) = self.token_stream.peek().map(|t| t.kind) {
+            self.next_token();
+            Ok(name)
+        }
+        else {
+            Err(self.unexpected("field name"))
         }
     }
 
-    fn parse_if(&mut self) -> AST {
-        self.skip_keyword(Keyword::If);
-        let condition = self.parse_expression();
-        self.skip_keyword(Keyword::Then);
-        let then = self.parse_expression();
+    fn parse_boolean(&mut self) -> Result<AST, ParseError> {
+        let kind = match self.token_stream.peek().map(|t| t.kind) {
+            Some(TokenKind::Keyword(Keyword::True)) => ASTKind::Boolean(true),
+            Some(TokenKind::Keyword(Keyword::False))=> ASTKind::Boolean(false),
+            _ => return Err(self.unexpected("boolean"))
+        };
+        self.next_token();
+        Ok(AST::new(kind, self.previous))
+    }
+
+    fn parse_if(&mut self) -> Result<AST, ParseError> {
+        let start = self.start();
+        self.skip_keyword(Keyword::If)?;
+        let condition = self.parse_expression()?;
+        self.skip_keyword(Keyword::Then)?;
+        let then = self.parse_expression()?;
         let otherwise = if self.is_keyword(Keyword::Else) {
-            self.token_stream.next();
-            Some(Box::new(self.parse_expression()))
+            self.next_token();
+            Some(Box::new(self.parse_expression()?))
         }
         else {
             None
         };
-        AST::If {
+        Ok(AST::new(ASTKind::If {
             condition: Box::new(condition),
             then: Box::new(then),
             otherwise
+        }, self.finish(start)))
+    }
+
+    /// Loop bodies must be blocks, so the end of the condition is clear.
+    fn parse_while(&mut self) -> Result<AST, ParseError> {
+        let start = self.start();
+        self.skip_keyword(Keyword::While)?;
+        let condition = self.parse_expression()?;
+        let body = self.parse_loop_body()?;
+        Ok(AST::new(ASTKind::While {
+            condition: Box::new(condition),
+            body: Box::new(body)
+        }, self.finish(start)))
+    }
+
+    fn parse_for(&mut self) -> Result<AST, ParseError> {
+        let start = self.start();
+        self.skip_keyword(Keyword::For)?;
+        let variable = self.parse_variable()?;
+        self.skip_keyword(Keyword::In)?;
+        let iterable = self.parse_expression()?;
+        let body = self.parse_loop_body()?;
+        Ok(AST::new(ASTKind::For {
+            variable,
+            iterable: Box::new(iterable),
+            body: Box::new(body)
+        }, self.finish(start)))
+    }
+
+    fn parse_loop_body(&mut self) -> Result<AST, ParseError> {
+        if !self.is_punctuation('{') {
+            return Err(self.unexpected("`{`"));
         }
+        let in_loop = mem::replace(&mut self.in_loop, true);
+        let body = self.parse_program();
+        self.in_loop = in_loop;
+        body
     }
 
-    fn parse_function(&mut self) -> AST {
-        AST::Function {
-            parameters: self.delimited_identifiers('(', ')', ','),
-            body: Box::new(self.parse_expression()),
-            native: None
+    /// Parses `break`, `continue` or `return`. Using one in the wrong place
+    /// is reported, but parsing carries on as if it were allowed.
+    fn parse_jump(&mut self) -> Result<AST, ParseError> {
+        let keyword = match self.next_token().map(|t| t.kind) {
+            Some(TokenKind::Keyword(keyword)) => keyword,
+            _ => unreachable!("`parse_jump` is only called on a keyword")
+        };
+        let span = self.previous;
+        let allowed = if keyword == Keyword::Return { self.in_function } else { self.in_loop };
+        if !allowed {
+            self.errors.push(ParseError::new(ParseErrorKind::Misplaced(keyword.clone()), span));
         }
+        let kind = match keyword {
+            Keyword::Break => ASTKind::Break,
+            Keyword::Continue => ASTKind::Continue,
+            _ if self.ends_expression() => ASTKind::Return(None),
+            _ => ASTKind::Return(Some(Box::new(self.parse_expression()?)))
+        };
+        Ok(AST::new(kind, self.finish(span.start)))
     }
 
-    fn parse_variable(&mut self) -> String {
-        let identifier = self.token_stream.next();
-        if let Some(Token::Identifier(name)) = identifier {
-            name
+    fn parse_function(&mut self, start: Position) -> Result<AST, ParseError> {
+        let parameters = self.delimited_identifiers('(', ')', ',')?;
+        let in_loop = mem::replace(&mut self.in_loop, false);
+        let in_function = mem::replace(&mut self.in_function, true);
+        let body = self.parse_expression();
+        self.in_loop = in_loop;
+        self.in_function = in_function;
+        let body = Box::new(body?);
+        Ok(AST::new(ASTKind::Function {
+            parameters,
+            body,
+            doc: None
+        }, self.finish(start)))
+    }
+
+    /// Parses `let a = 1, b = 2 in body`.
+    fn parse_let(&mut self) -> Result<AST, ParseError> {
+        let start = self.start();
+        let variables = self.parse_let_variables()?;
+        self.skip_keyword(Keyword::In)?;
+        let body = Box::new(self.parse_expression()?);
+        Ok(AST::new(ASTKind::Let { variables, body }, self.finish(start)))
+    }
+
+    fn parse_let_variables(&mut self) -> Result<Vec<(String, AST)>, ParseError> {
+        self.skip_keyword(Keyword::Let)?;
+        let mut variables = Vec::new();
+        loop {
+            let name = self.parse_variable()?;
+            self.skip_operator("=")?;
+            variables.push((name, self.parse_expression()?));
+            if !self.is_punctuation(',') {
+                return Ok(variables);
+            }
+            self.next_token();
+        }
+    }
+
+    fn parse_variable(&mut self) -> Result<String, ParseError> {
+        if let Some(TokenKind::Identifier(name)) = self.token_stream.peek().map(|t| t.kind) {
+            self.next_token();
+            Ok(name)
         }
         else {
-            self.token_stream.panic(format!("Expected variable name, got: {:?}", identifier))
+            Err(self.unexpected("variable name"))
         }
     }
 
-    fn maybe_binary(&mut self, left: AST, prec_left: i8) -> AST {
+    fn maybe_binary(&mut self, left: AST, prec_left: i8) -> Result<AST, ParseError> {
         if let Some(operator) = self.is_operator() {
-            let prec_right = precedence(operator.clone());
+            // a prefix-only operator like `!` ends the expression, whatever
+            // comes next will report it
+            let (prec_right, associativity) = match precedence(&operator) {
+                Some(prec) => prec,
+                None => return Ok(left)
+            };
             if prec_right > prec_left {
-                self.token_stream.next();
-                let atom = self.parse_atom();
-                let next_bin = self.maybe_binary(atom, prec_right);
-                self.maybe_binary(match operator.clone().as_ref() {
-                    "=" =>
-                        AST::Assign {
+                self.next_token();
+                let atom = self.parse_atom()?;
+                // let an operator of the same precedence take the right hand
+                // side, so `a ** b ** c` is `a ** (b ** c)`
+                let next_prec = match associativity {
+                    Associativity::Left => prec_right,
+                    Associativity::Right => prec_right - 1
+                };
+                let next_bin = self.maybe_binary(atom, next_prec)?;
+                let span = left.span.to(next_bin.span);
+                let kind = match operator.as_ref() {
+                    "=" | ":=" =>
+                        ASTKind::Assign {
                             operator,
                             left: Box::new(left),
-                            right: Box::new(next_bin)
+                            right: Box::new(next_bin),
+                            doc: None
                         },
                     op =>
-                        AST::Binary {
+                        ASTKind::Binary {
                             operator: op.to_string(),
                             left: Box::new(left),
                             right: Box::new(next_bin)
                         }
-                }, prec_left)
+                };
+                self.maybe_binary(AST::new(kind, span), prec_left)
             }
             else {
-                left
+                Ok(left)
             }
         }
         else {
-            left
+            Ok(left)
         }
     }
 
-    fn delimited_expressions(&mut self, start: char, end: char, separator: char) -> Vec<AST> {
-        let mut output: Vec<AST> = Vec::new();
+    /// Parses a bracketed list of items with `parse`, replacing any that fail
+    /// to parse with `error`.
+    fn delimited<T>(&mut self, start: char, end: char, separator: char,
+                    parse: fn(&mut Parser<'a>) -> Result<T, ParseError>,
+                    error: fn(Span) -> T) -> Result<Vec<T>, ParseError> {
+        let mut output: Vec<T> = Vec::new();
         let mut first = true;
 
-        self.skip_punctuation(start);
+        self.skip_punctuation(start)?;
+        let opening = self.previous;
 
         while !self.token_stream.eof() {
             if self.is_punctuation(end) {
@@ -248,24 +592,47 @@ impl<'a> Parser<'a> {
             if first {
                 first = false;
             }
+            else if !self.is_punctuation(separator) {
+                let error = self.unexpected(&format!("`{}` or `{}`", separator, end));
+                self.recover(error);
+                if self.is_punctuation(separator) {
+                    self.next_token();
+                }
+                else if !self.is_punctuation(end) {
+                    return Ok(output);
+                }
+            }
             else {
-                self.skip_punctuation(separator);
+                self.next_token();
             }
             if self.is_punctuation(end) {
                 break;
             }
-            output.push(self.parse_expression());
+            let item_start = self.start();
+            match parse(self) {
+                Ok(item) => output.push(item),
+                Err(parse_error) => {
+                    self.recover(parse_error);
+                    output.push(error(self.finish(item_start)));
+                    // the error has been reported, so don't complain again
+                    // if recovery stopped somewhere outside of this list
+                    if !self.is_punctuation(separator) && !self.is_punctuation(end) {
+                        return Ok(output);
+                    }
+                }
+            }
         }
 
-        self.skip_punctuation(end);
-        output
+        self.skip_closing(end, opening)?;
+        Ok(output)
     }
 
-    fn delimited_identifiers(&mut self, start: char, end: char, separator: char) -> Vec<String> {
+    fn delimited_identifiers(&mut self, start: char, end: char, separator: char) -> Result<Vec<String>, ParseError> {
         let mut output: Vec<String> = Vec::new();
         let mut first = true;
 
-        self.skip_punctuation(start);
+        self.skip_punctuation(start)?;
+        let opening = self.previous;
 
         while !self.token_stream.eof() {
             if self.is_punctuation(end) {
@@ -275,38 +642,124 @@ impl<'a> Parser<'a> {
                 first = false;
             }
             else {
-                self.skip_punctuation(separator);
+                self.skip_punctuation(separator)?;
             }
             if self.is_punctuation(end) {
                 break;
             }
-            output.push(self.parse_variable());
+            output.push(self.parse_variable()?);
         }
 
-        self.skip_punctuation(end);
-        output
+        self.skip_closing(end, opening)?;
+        Ok(output)
     }
 
-    fn is_punctuation(&mut self, punc: char) -> bool {
-        if let Some(Token::Punctuation(p)) = self.token_stream.peek() {
-            punc == p
+    /// Records `error` and skips ahead to a point where parsing can resume.
+    fn recover(&mut self, error: ParseError) {
+        self.errors.push(error);
+        self.synchronize();
+    }
+
+    /// Skips tokens up to a `;` or a closing bracket that was opened before
+    /// the current position. The token it stops on is not consumed.
+    fn synchronize(&mut self) {
+        let mut depth = 0;
+        while let Some(token) = self.token_stream.peek() {
+            match token.kind {
+                TokenKind::Punctuation(';') if depth == 0 => break,
+                TokenKind::Punctuation('}') | TokenKind::Punctuation(')') | TokenKind::Punctuation(']') => {
+                    if depth == 0 {
+                        break;
+                    }
+                    depth -= 1;
+                },
+                TokenKind::Punctuation('{') | TokenKind::Punctuation('(') | TokenKind::Punctuation('[') => {
+                    depth += 1;
+                },
+                _ => {}
+            }
+            self.next_token();
         }
-        else {
-            false
+    }
+
+    /// An error for the upcoming token, which is not the `expected` one.
+    fn unexpected(&mut self, expected: &str) -> ParseError {
+        match self.token_stream.peek() {
+            Some(token) => ParseError::unexpected(expected, Some(token.kind), token.span),
+            None => ParseError::unexpected(expected, None, Span::new(self.previous.end, self.previous.end))
         }
     }
 
-    fn is_keyword(&mut self, keyword: Keyword) -> bool {
-        if let Some(Token::Keyword(k)) = self.token_stream.peek() {
-            k == keyword
+    /// Consumes the next token, remembering its span so the node being
+    /// parsed can be given a span ending there.
+    fn next_token(&mut self) -> Option<Token> {
+        let token = self.token_stream.next();
+        if let Some(ref token) = token {
+            self.previous = token.span;
+        }
+        token
+    }
+
+    /// The doc comment before the next token.
+    fn doc(&mut self) -> Option<String> {
+        self.token_stream.peek().and_then(|token| token.doc)
+    }
+
+    /// The position where the next node will start.
+    fn start(&mut self) -> Position {
+        match self.token_stream.peek() {
+            Some(token) => token.span.start,
+            None => self.previous.end
+        }
+    }
+
+    /// The span from `start` up to the end of the last consumed token.
+    fn finish(&self, start: Position) -> Span {
+        if self.previous.end.offset < start.offset {
+            Span::new(start, start)
         }
         else {
-            false
+            Span::new(start, self.previous.end)
+        }
+    }
+
+    fn is_punctuation(&mut self, punc: char) -> bool {
+        matches!(self.token_stream.peek(), Some(Token { kind: TokenKind::Punctuation(p), .. }) if p == punc)
+    }
+
+    /// Whether a map literal is next, rather than a block. Maps are either
+    /// empty or start with a key and a `:`, which is further ahead than the
+    /// token stream can peek, so the start is read again separately.
+    fn is_map(&mut self) -> bool {
+        if !self.is_punctuation('{') {
+            return false;
+        }
+        let mut lookahead = TokenStream::new(InputStream::starting_at(self.token_stream.source(), self.start()));
+        lookahead.next();
+        match lookahead.next().map(|t| t.kind) {
+            Some(TokenKind::Punctuation('}')) => true,
+            Some(TokenKind::Identifier(_)) | Some(TokenKind::String_(_)) =>
+                matches!(lookahead.next(), Some(Token { kind: TokenKind::Punctuation(':'), .. })),
+            _ => false
         }
     }
 
+    /// Whether the next token can't start an expression, so a `return`
+    /// before it has no value.
+    fn ends_expression(&mut self) -> bool {
+        match self.token_stream.peek().map(|t| t.kind) {
+            None | Some(TokenKind::Keyword(Keyword::Else)) => true,
+            Some(TokenKind::Punctuation(c)) => matches!(c, ';' | ',' | ')' | '}' | ']'),
+            _ => false
+        }
+    }
+
+    fn is_keyword(&mut self, keyword: Keyword) -> bool {
+        matches!(self.token_stream.peek(), Some(Token { kind: TokenKind::Keyword(k), .. }) if k == keyword)
+    }
+
     fn is_operator(&mut self) -> Option<String> {
-        if let Some(Token::Operator(op)) = self.token_stream.peek() {
+        if let Some(Token { kind: TokenKind::Operator(op), .. }) = self.token_stream.peek() {
             Some(op)
         }
         else {
@@ -314,33 +767,145 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn skip_keyword(&mut self, keyword: Keyword) {
+    fn skip_keyword(&mut self, keyword: Keyword) -> Result<(), ParseError> {
         if self.is_keyword(keyword.clone()) {
-            self.token_stream.next();
+            self.next_token();
+            Ok(())
         }
         else {
-            self.token_stream.panic(format!("Expected keyword {:?}", keyword));
+            Err(self.unexpected(&format!("keyword `{}`", keyword)))
         }
     }
 
-    fn skip_punctuation(&mut self, punc: char) {
+    fn skip_punctuation(&mut self, punc: char) -> Result<(), ParseError> {
         if self.is_punctuation(punc) {
-            self.token_stream.next();
+            self.next_token();
+            Ok(())
+        }
+        else {
+            Err(self.unexpected(&format!("`{}`", punc)))
+        }
+    }
+
+    fn skip_operator(&mut self, operator: &str) -> Result<(), ParseError> {
+        if self.is_operator().as_deref() == Some(operator) {
+            self.next_token();
+            Ok(())
         }
         else {
-            self.token_stream.panic(format!("Expected punctuation {}", punc));
+            Err(self.unexpected(&format!("`{}`", operator)))
         }
     }
+
+    /// Like `skip_punctuation`, but for the bracket closing `opening`.
+    fn skip_closing(&mut self, punc: char, opening: Span) -> Result<(), ParseError> {
+        self.skip_punctuation(punc).map_err(|mut error| {
+            error.kind = ParseErrorKind::Unclosed(opening);
+            error
+        })
+    }
 }
 
-fn precedence(op: String) -> i8 {
-    match op.as_ref() {
-        "=" => 1,
-        "||" => 2,
-        "&&" => 3,
-        "<" | ">" | "<=" | ">=" | "==" | "!=" => 7,
-        "+" | "-" => 10,
-        "*" | "/" | "%" => 20,
-        _ => panic!(format!("Unknown operator: {}", op))
+/// Keeps a doc comment on the assignment or function it was written before.
+/// Doc comments before anything else are dropped.
+fn attach_doc(ast: &mut AST, doc: Option<String>) {
+    match ast.kind {
+        ASTKind::Assign { doc: ref mut slot, .. } | ASTKind::Function { doc: ref mut slot, .. } => *slot = doc,
+        _ => {}
+    }
+}
+
+/// Gives each `let` without `in` the rest of its block as a body, so the
+/// variables are only in scope from the declaration to the end of the block.
+fn scope_statements(statements: Vec<Statement>) -> Vec<AST> {
+    let mut output: Vec<AST> = Vec::new();
+    for statement in statements.into_iter().rev() {
+        match statement {
+            Statement::Expression(expression) => output.push(expression),
+            Statement::Let(variables, span) => {
+                output.reverse();
+                let body_span = match (output.first(), output.last()) {
+                    (Some(first), Some(last)) => first.span.to(last.span),
+                    _ => Span::new(span.end, span.end)
+                };
+                let body = AST::new(ASTKind::Program(mem::take(&mut output)), body_span);
+                output.push(AST::new(ASTKind::Let { variables, body: Box::new(body) }, span.to(body_span)));
+            }
+        }
+    }
+    output.reverse();
+    output
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Associativity {
+    Left,
+    Right
+}
+
+/// The binary operators, how tightly each binds (higher binds tighter) and
+/// which way a chain of them groups.
+const BINARY_OPERATORS: &[(&str, i8, Associativity)] = &[
+    ("=",  1, Associativity::Right),
+    (":=", 1, Associativity::Right),
+    ("||", 2, Associativity::Left),
+    ("&&", 3, Associativity::Left),
+    ("==", 4, Associativity::Left),
+    ("!=", 4, Associativity::Left),
+    ("<",  4, Associativity::Left),
+    (">",  4, Associativity::Left),
+    ("<=", 4, Associativity::Left),
+    (">=", 4, Associativity::Left),
+    ("..", 5, Associativity::Left),
+    ("|",  6, Associativity::Left),
+    ("^",  7, Associativity::Left),
+    ("&",  8, Associativity::Left),
+    ("<<", 9, Associativity::Left),
+    (">>", 9, Associativity::Left),
+    ("+",  10, Associativity::Left),
+    ("-",  10, Associativity::Left),
+    ("*",  11, Associativity::Left),
+    ("/",  11, Associativity::Left),
+    ("//", 11, Associativity::Left),
+    ("%",  11, Associativity::Left),
+    ("**", 13, Associativity::Right)
+];
+
+/// Prefix operators bind tighter than any binary operator except `**`, so
+/// `-a * b` is `(-a) * b` but `-a ** b` is `-(a ** b)`.
+const PREFIX_PRECEDENCE: i8 = 12;
+
+fn is_prefix(op: &str) -> bool {
+    matches!(op, "-" | "+" | "!")
+}
+
+fn precedence(op: &str) -> Option<(i8, Associativity)> {
+    BINARY_OPERATORS.iter()
+        .find(|&&(operator, _, _)| operator == op)
+        .map(|&(_, prec, associativity)| (prec, associativity))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn errors(code: &str) -> Vec<ParseError> {
+        Parser::new(TokenStream::new(InputStream::new(code))).parse_partial().1
+    }
+
+    #[test]
+    fn missing_separator_is_not_an_unclosed_bracket() {
+        let errors = errors("f(a b)");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].kind, ParseErrorKind::UnexpectedToken);
+    }
+
+    #[test]
+    fn interpolation_at_end_of_input_has_spans_in_the_source() {
+        for code in &["\"${", "x := \"${"] {
+            let errors = errors(code);
+            assert!(matches!(errors[0].kind, ParseErrorKind::UnterminatedString(_)));
+            assert!(errors.iter().all(|error| error.span.start.line == 1 && error.span.start.column > 0));
+        }
     }
 }